@@ -0,0 +1,337 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs::{self,File};
+use std::future::Future;
+use std::io::{BufRead,BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component,Path,PathBuf};
+use chrono::{DateTime,Local};
+use crate::{AiError,FolderListingType,Location,Thumbnails};
+
+/// How many tools the assistant can use before it has to give an answer
+const AGENT_MAX_STEPS: usize = 8;
+
+/// Observations are cut off after this many characters so they don't crowd out the question
+const AGENT_MAX_OBSERVATION: usize = 2000;
+
+const READ_FILE_MAX_LINES: usize = 40;
+const SEARCH_MAX_DEPTH: usize = 5;
+const SEARCH_MAX_RESULTS: usize = 50;
+
+/// What the match arms of a prompt echoed before its answer
+#[derive(Debug, PartialEq)]
+pub enum PromptOutcome {
+    Success(String),
+    Failure(String),
+    /// A step the agent should take before answering
+    Action(String)
+}
+
+impl PromptOutcome {
+    /// Only the start of the output decides the outcome, so answers that have colons or
+    /// "FAILURE:" in them are taken as they are
+    pub fn parse(output: &str) -> Option<Self> {
+        let answer = |prefix: &str| output.strip_prefix(prefix).map(|answer| answer.trim().to_string());
+
+        answer("SUCCESS:").map(PromptOutcome::Success)
+            .or_else(|| answer("FAILURE:").map(PromptOutcome::Failure))
+            .or_else(|| answer("ACTION:").map(PromptOutcome::Action))
+    }
+}
+
+/// Drives the Thought/Action/Observation protocol of a prompt, answering the actions it asks for
+/// with built in tools that inspect the current folder
+#[derive(Clone)]
+pub struct Agent {
+    location: Location,
+    thumbnails: Thumbnails
+}
+
+impl Agent {
+    pub fn new(location: Location, thumbnails: Thumbnails) -> Self {
+        Self { location, thumbnails }
+    }
+
+    /// Runs `prompt` until it gives something other than an action. Actions are reported by the
+    /// prompt as "ACTION:" followed by its output, every other output is returned as is. An error
+    /// running the prompt ends the run.
+    pub async fn run<F, Fut>(&self, root: &Path, input: String, mut prompt: F) -> Result<String, AiError>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<String, AiError>>
+    {
+        let mut transcript = input;
+
+        for _ in 0..AGENT_MAX_STEPS {
+            let output = prompt(transcript.clone()).await?;
+
+            let step = match PromptOutcome::parse(&output) {
+                Some(PromptOutcome::Action(step)) => step,
+                _ => return Ok(output)
+            };
+
+            let observation = match parse_action(&step) {
+                Some((action, input)) => self.dispatch(root, &action, &input),
+                None => String::from("The action couldn't be understood")
+            };
+
+            transcript = format!("{transcript}\n{step}\nObservation -> {}", truncate(observation));
+        }
+
+        Ok(format!("FAILURE:I couldn't come up with a script after {AGENT_MAX_STEPS} steps, can you \
+            try asking in a different way?"))
+    }
+
+    fn dispatch(&self, root: &Path, action: &str, input: &str) -> String {
+        let path = match resolve(root, input) {
+            Ok(path) => path,
+            Err(e) if action != "Search" => return e,
+            Err(_) => root.to_path_buf()
+        };
+
+        match action {
+            "ListFolder" => self.list_folder(&path),
+            "ReadFile" => read_file(&path),
+            "Stat" => stat(&path),
+            "Search" => search(root, input),
+            "Thumbnail" => self.thumbnail(&path),
+            _ => format!("There's no tool called {action}")
+        }
+    }
+
+    fn list_folder(&self, path: &Path) -> String {
+        if !path.is_dir() {
+            return format!("{} isn't a folder", path.display());
+        }
+
+        let folder = self.location.list(path);
+        if folder.files.is_empty() {
+            return String::from("The folder is empty");
+        }
+
+        folder.files.iter()
+            .map(|listing| match listing.kind {
+                FolderListingType::Folder => format!("{}/", listing.name),
+                FolderListingType::Link => format!("{} (link)", listing.name),
+                FolderListingType::File => listing.name.clone()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn thumbnail(&self, path: &Path) -> String {
+        match image::image_dimensions(path) {
+            Ok((width, height)) => {
+                let cached = if self.thumbnails.url_from(path).is_some() {
+                    "a thumbnail has been generated"
+                } else {
+                    "no thumbnail has been generated"
+                };
+                format!("{width}x{height} image, {cached}")
+            },
+            Err(e) => format!("{} isn't an image that can be read: {e}", path.display())
+        }
+    }
+}
+
+/// The action and its input from the last step of the assistant's output
+fn parse_action(step: &str) -> Option<(String, String)> {
+    let start = step.rfind("Action ->")?;
+    let rest = &step[start + "Action ->".len()..];
+    let (action, rest) = rest.split_once('\n').unwrap_or((rest, ""));
+    let input = rest.split_once("Action Input ->")
+        .map(|(_, input)| input.trim())
+        .unwrap_or("");
+
+    Some((action.trim().trim_matches(|c| c == '[' || c == ']').to_string(), input.to_string()))
+}
+
+/// Resolves a path given to a tool against `root`, refusing paths that lead outside of it, including
+/// through links
+fn resolve(root: &Path, input: &str) -> Result<PathBuf, String> {
+    let input = input.trim().trim_matches(|c| c == '"' || c == '\'');
+    let relative = Path::new(input).strip_prefix(root).unwrap_or(Path::new(input));
+    let mut path = root.to_path_buf();
+
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {},
+            Component::ParentDir if path != root => { path.pop(); },
+            _ => return Err(format!("{input} isn't inside of {}", root.display()))
+        }
+    }
+
+    // The closest part of the path that exists is where a link would lead
+    let Ok(real_root) = root.canonicalize() else {
+        return Ok(path);
+    };
+    let real = path.ancestors().find_map(|ancestor| ancestor.canonicalize().ok());
+
+    match real {
+        Some(real) if !real.starts_with(&real_root) => {
+            Err(format!("{input} leads outside of {}", root.display()))
+        },
+        _ => Ok(path)
+    }
+}
+
+fn read_file(path: &Path) -> String {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return format!("{} couldn't be read: {e}", path.display())
+    };
+
+    let lines = BufReader::new(file)
+        .split(b'\n')
+        .take(READ_FILE_MAX_LINES)
+        .filter_map(|line| line.ok())
+        .map(|line| String::from_utf8_lossy(&line).into_owned())
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        String::from("The file is empty")
+    } else {
+        lines.join("\n")
+    }
+}
+
+fn stat(path: &Path) -> String {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return format!("{} couldn't be found: {e}", path.display())
+    };
+
+    let kind = if metadata.is_dir() {
+        "folder"
+    } else if metadata.is_symlink() {
+        "link"
+    } else {
+        "file"
+    };
+
+    let modified = metadata.modified()
+        .map(|modified| DateTime::<Local>::from(modified).to_rfc3339())
+        .unwrap_or_else(|_| String::from("unknown"));
+
+    format!("{kind}, {} bytes, permissions {:o}, modified {modified}",
+        metadata.len(),
+        metadata.permissions().mode() & 0o7777)
+}
+
+/// Finds files in `root` and its subfolders whose name contains `query`, ignoring case
+fn search(root: &Path, query: &str) -> String {
+    let query = query.trim().trim_matches(|c| c == '"' || c == '\'').to_lowercase();
+    let mut results = vec![];
+    let mut pending = vec![(root.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if entry.file_name().to_string_lossy().to_lowercase().contains(&query) {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                results.push(relative.display().to_string());
+
+                if results.len() == SEARCH_MAX_RESULTS {
+                    results.push(String::from("..."));
+                    return results.join("\n");
+                }
+            }
+
+            if depth < SEARCH_MAX_DEPTH && entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                pending.push((path, depth + 1));
+            }
+        }
+    }
+
+    if results.is_empty() {
+        format!("Nothing matching {query} was found")
+    } else {
+        results.join("\n")
+    }
+}
+
+fn truncate(mut observation: String) -> String {
+    if observation.len() > AGENT_MAX_OBSERVATION {
+        let mut end = AGENT_MAX_OBSERVATION;
+        while !observation.is_char_boundary(end) {
+            end -= 1;
+        }
+        observation.truncate(end);
+        observation.push_str("\n...");
+    }
+
+    observation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::APP_NAME;
+
+    #[test]
+    fn agent_parse_action() {
+        let step = "Thought -> I should look at the files\nAction -> ReadFile\nAction Input -> notes/a.md";
+
+        assert_eq!(parse_action(step), Some(("ReadFile".into(), "notes/a.md".into())));
+        assert_eq!(parse_action("Action -> [Search]\nAction Input -> \"png\""),
+            Some(("Search".into(), "\"png\"".into())));
+        assert_eq!(parse_action("Thought -> Hmm"), None);
+    }
+
+    #[test]
+    fn agent_prompt_outcome() {
+        assert_eq!(PromptOutcome::parse("SUCCESS:echo \"FAILURE: a:b\"\n"),
+            Some(PromptOutcome::Success("echo \"FAILURE: a:b\"".into())));
+        assert_eq!(PromptOutcome::parse("FAILURE:42"), Some(PromptOutcome::Failure("42".into())));
+        assert_eq!(PromptOutcome::parse("ACTION:Thought -> Hmm"),
+            Some(PromptOutcome::Action("Thought -> Hmm".into())));
+        assert_eq!(PromptOutcome::parse("Note: SUCCESS:ls"), None);
+    }
+
+    #[test]
+    fn agent_resolve() {
+        let root = Path::new("/home/user/docs");
+
+        assert_eq!(resolve(root, "a/b.txt"), Ok(PathBuf::from("/home/user/docs/a/b.txt")));
+        assert_eq!(resolve(root, "/home/user/docs/a"), Ok(PathBuf::from("/home/user/docs/a")));
+        assert_eq!(resolve(root, "a/../b"), Ok(PathBuf::from("/home/user/docs/b")));
+        assert_eq!(resolve(root, "."), Ok(PathBuf::from("/home/user/docs")));
+        assert!(resolve(root, "../secrets").is_err());
+        assert!(resolve(root, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn agent_resolve_links() {
+        let dir = std::env::temp_dir().join(format!("{APP_NAME}-agent-test-{}", std::process::id()));
+        let root = dir.join("root");
+        fs::create_dir_all(root.join("inside")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        fs::write(dir.join("outside/secret"), "x").unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(root.join("inside"), root.join("within")).unwrap();
+
+        assert!(resolve(&root, "escape").is_err());
+        assert!(resolve(&root, "escape/secret").is_err());
+        assert!(resolve(&root, "escape/missing").is_err());
+        assert_eq!(resolve(&root, "within"), Ok(root.join("within")));
+        assert_eq!(resolve(&root, "inside/missing"), Ok(root.join("inside/missing")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
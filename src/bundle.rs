@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+use std::path::{Path,PathBuf};
+use crate::models::{ActionBundle,ActionImport,BundledAction};
+use crate::store::Store;
+
+/// The version of the bundle format written by `export`, bumped whenever a change to it would
+/// keep an older version of Aerome from reading it
+pub const ACTION_BUNDLE_VERSION: u32 = 1;
+
+/// Writes the actions saved in folders under `prefix`, or every action, to a bundle at `to`.
+/// Bundles are TOML when the file name ends with ".toml", JSON otherwise. Returns how many
+/// actions were written.
+pub fn export(store: &Store, to: &Path, prefix: Option<&Path>) -> Result<usize, String> {
+    let home = dirs::home_dir();
+    let actions = store.export_actions(prefix).into_iter()
+        .map(|action| BundledAction {
+            path: portable(&action.path, home.as_deref()),
+            ..action
+        })
+        .collect::<Vec<_>>();
+    let count = actions.len();
+    let bundle = ActionBundle {
+        version: ACTION_BUNDLE_VERSION,
+        actions
+    };
+
+    let contents = if is_toml(to) {
+        toml::to_string_pretty(&bundle).map_err(|e| e.to_string())?
+    } else {
+        serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?
+    };
+
+    fs::write(to, contents).map_err(|e| format!("Couldn't write {}: {e}", to.display()))?;
+    Ok(count)
+}
+
+/// Saves the actions in the bundle at `from`, see `Store::import_actions`
+pub fn import(store: &Store, from: &Path, replace: bool) -> Result<ActionImport, String> {
+    let contents = fs::read_to_string(from)
+        .map_err(|e| format!("Couldn't read {}: {e}", from.display()))?;
+    let bundle = parse(&contents, is_toml(from))?;
+    let home = dirs::home_dir();
+    let actions = bundle.actions.into_iter()
+        .map(|action| BundledAction {
+            path: local(&action.path, home.as_deref()),
+            ..action
+        })
+        .collect::<Vec<_>>();
+
+    Ok(store.import_actions(&actions, replace))
+}
+
+fn parse(contents: &str, toml: bool) -> Result<ActionBundle, String> {
+    let bundle: ActionBundle = if toml {
+        toml::from_str(contents).map_err(|e| e.to_string())?
+    } else {
+        serde_json::from_str(contents).map_err(|e| e.to_string())?
+    };
+
+    if bundle.version > ACTION_BUNDLE_VERSION {
+        return Err(format!(
+            "The bundle is version {}, this version of Aerome only understands up to version {}",
+            bundle.version,
+            ACTION_BUNDLE_VERSION));
+    }
+
+    Ok(bundle)
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().map(|extension| extension == "toml").unwrap_or(false)
+}
+
+/// `path` with the home folder replaced by "~"
+fn portable(path: &Path, home: Option<&Path>) -> PathBuf {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => PathBuf::from("~"),
+        Some(relative) => Path::new("~").join(relative),
+        None => path.to_path_buf()
+    }
+}
+
+/// `path` with a leading "~" replaced by the home folder
+fn local(path: &Path, home: Option<&Path>) -> PathBuf {
+    match (path.strip_prefix("~"), home) {
+        (Ok(relative), Some(home)) if relative.as_os_str().is_empty() => home.to_path_buf(),
+        (Ok(relative), Some(home)) => home.join(relative),
+        _ => path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ActionScope;
+
+    #[test]
+    fn bundle_formats() {
+        let bundle = ActionBundle {
+            version: ACTION_BUNDLE_VERSION,
+            actions: vec![ BundledAction {
+                path: "~/Pictures".into(),
+                code: "rm *.tmp".into(),
+                question: "Remove temporary files".into(),
+                description: "Clean up".into(),
+                scope: ActionScope::Pattern { glob: "~/Pictures/*".into() }
+            } ]
+        };
+
+        let json = serde_json::to_string(&bundle).unwrap();
+        let toml = toml::to_string(&bundle).unwrap();
+        assert_eq!(parse(&json, false), Ok(bundle.clone()));
+        assert_eq!(parse(&toml, true), Ok(bundle));
+
+        assert!(parse(r#"{ "version": 99, "actions": [] }"#, false).is_err());
+        assert_eq!(parse(r#"{ "version": 1, "actions": [{ "path": "/a", "code": "ls", "question": "List" }] }"#, false)
+            .unwrap().actions[0].scope, ActionScope::Folder);
+    }
+
+    #[test]
+    fn bundle_home_paths() {
+        let home = Some(Path::new("/home/someone"));
+
+        assert_eq!(portable(Path::new("/home/someone/Pictures"), home), PathBuf::from("~/Pictures"));
+        assert_eq!(portable(Path::new("/srv/data"), home), PathBuf::from("/srv/data"));
+        assert_eq!(local(Path::new("~/Pictures"), Some(Path::new("/home/other"))),
+            PathBuf::from("/home/other/Pictures"));
+        assert_eq!(local(Path::new("/srv/data"), home), PathBuf::from("/srv/data"));
+    }
+}
@@ -220,14 +220,58 @@ fn install_desktop_files() {
 #[cfg(not(target_os = "linux"))]
 fn install_desktop_files() {}
 
-fn install_prompts() {
-    let prompts_dir = dirs::data_local_dir()
+/// Where the prompts are installed
+pub fn prompts_dir() -> PathBuf {
+    dirs::data_local_dir()
         .map(|data_dir| data_dir.join(APP_NAME).join("prompts"))
-        .expect("Could not find the apps data directory");
+        .expect("Could not find the apps data directory")
+}
+
+/// Where prompts can be put to be used instead of the installed ones, they're never overwritten
+pub fn prompt_overrides_dir() -> PathBuf {
+    dirs::config_dir()
+        .map(|config_dir| config_dir.join(APP_NAME).join("prompts"))
+        .expect("Could not find the apps config directory")
+}
+
+/// The prompt to use for `name`, an override if there is one and the installed one otherwise
+pub fn prompt_path(name: &str) -> PathBuf {
+    let overridden = prompt_overrides_dir().join(name);
+
+    if overridden.exists() {
+        overridden
+    } else {
+        prompts_dir().join(name)
+    }
+}
+
+/// Installs each prompt that's missing or older than the one Aerome ships with, so changes made
+/// to an installed prompt last until there's a newer version of it
+fn install_prompts() {
+    let prompts_dir = prompts_dir();
 
     fs::create_dir_all(&prompts_dir).expect("Could not write to the apps data directory");
-    fs::write(prompts_dir.join("./communicate.pr"), PROMPT_COMMUNICATE).unwrap();
-    fs::write(prompts_dir.join("./summary.pr"), PROMPT_SUMMARY).unwrap();
+    let _ = fs::create_dir_all(prompt_overrides_dir());
+
+    for (name, prompt) in PROMPTS {
+        let path = prompts_dir.join(name);
+        let installed = fs::read(&path).ok().map(|installed| prompt_version(&installed));
+
+        if installed.map(|version| version < prompt_version(prompt)).unwrap_or(true) {
+            fs::write(path, prompt).unwrap();
+        }
+    }
+}
+
+/// The version in the header of a prompt, like " * Version: 2". Prompts from before they had
+/// versions are version 0.
+fn prompt_version(prompt: &[u8]) -> u32 {
+    String::from_utf8_lossy(prompt)
+        .lines()
+        .take_while(|line| !line.starts_with(" */"))
+        .find_map(|line| line.trim_start_matches(|c| c == ' ' || c == '*').strip_prefix("Version:"))
+        .and_then(|version| version.trim().parse().ok())
+        .unwrap_or(0)
 }
 
 fn install_icons() {
@@ -368,3 +412,18 @@ fn install_icons() {
         mimetypes_dir.join("./application-x-zip.png"),
         APP_ICON_MIMETYPE_APPLICATION_X_ZIP).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_have_versions() {
+        for (name, prompt) in PROMPTS {
+            assert!(prompt_version(prompt) > 0, "{name} is missing a version");
+        }
+
+        assert_eq!(prompt_version(b"/*\n * Version: 12\n */\nsummary"), 12);
+        assert_eq!(prompt_version(b"/*\n */\n * Version: 12"), 0);
+    }
+}
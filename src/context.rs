@@ -0,0 +1,290 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeMap,VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use chrono::{DateTime,Local};
+use xdg_mime::SharedMimeInfo;
+use crate::{Folder,FolderListingType};
+
+/// Roughly how many tokens the description of a folder can take up in a prompt
+const CONTEXT_TOKEN_BUDGET: usize = 1500;
+
+/// A rough rule of thumb for English text and file names
+const CHARS_PER_TOKEN: usize = 4;
+
+/// How deep the summary of subfolders goes below the current folder
+const CONTEXT_TREE_DEPTH: usize = 2;
+
+/// Describes a folder for the assistant: what's in it, how big and how old it is and what's in
+/// the folders below it, cut down to fit in a prompt
+#[derive(Clone)]
+pub struct FolderContext {
+    mime_db: Arc<SharedMimeInfo>
+}
+
+struct Entry {
+    name: String,
+    kind: FolderListingType,
+    mime: String,
+    size: u64,
+    modified: Option<SystemTime>
+}
+
+/// What's left of the token budget, counted in characters
+struct Budget(usize);
+
+impl Budget {
+    fn new(tokens: usize) -> Self {
+        Self(tokens * CHARS_PER_TOKEN)
+    }
+
+    /// Spends the budget on `line` if there's enough of it left
+    fn take(&mut self, line: &str) -> bool {
+        let cost = line.len() + 1;
+        if cost <= self.0 {
+            self.0 -= cost;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl FolderContext {
+    pub fn new(mime_db: SharedMimeInfo) -> Self {
+        Self {
+            mime_db: Arc::new(mime_db)
+        }
+    }
+
+    pub fn describe(&self, folder: &Folder, selected: &[String]) -> String {
+        let mut budget = Budget::new(CONTEXT_TOKEN_BUDGET);
+        let mut lines = vec![ format!("Folder -> {}", folder.path.display()) ];
+
+        if !selected.is_empty() {
+            lines.push(format!("Targets -> {}", selected.iter()
+                .map(|name| format!(r#""{name}""#))
+                .collect::<Vec<_>>()
+                .join(", ")));
+        }
+
+        lines.iter().for_each(|line| { budget.take(line); });
+
+        let entries = folder.files.iter()
+            .map(|listing| {
+                let path = folder.path.join(&listing.name);
+                self.entry(&path, listing.name.clone(), listing.kind.clone())
+            })
+            .collect::<Vec<_>>();
+
+        // The listing gets most of the budget, whatever it doesn't use goes to the subfolders
+        let mut listing_budget = Budget(budget.0 * 2 / 3);
+        budget.0 -= listing_budget.0;
+
+        lines.push(String::from("Contents ->"));
+        lines.extend(describe_entries(&entries, &mut listing_budget));
+        budget.0 += listing_budget.0;
+
+        let tree = self.describe_tree(&folder.path, &entries, &mut budget);
+        if !tree.is_empty() {
+            lines.push(String::from("Subfolders ->"));
+            lines.extend(tree);
+        }
+
+        lines.join("\n")
+    }
+
+    fn entry(&self, path: &Path, name: String, kind: FolderListingType) -> Entry {
+        let metadata = fs::metadata(path).ok();
+        let mime = match kind {
+            FolderListingType::Folder => String::from("folder"),
+            _ => self.mime_db.guess_mime_type()
+                .file_name(&name)
+                .guess()
+                .mime_type()
+                .essence_str()
+                .to_string()
+        };
+
+        Entry {
+            name,
+            kind,
+            mime,
+            size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: metadata.and_then(|m| m.modified().ok())
+        }
+    }
+
+    /// A line for every folder down to `CONTEXT_TREE_DEPTH`, breadth first so the folders closest
+    /// to the current one make it in when the budget runs out
+    fn describe_tree(&self, root: &Path, entries: &[Entry], budget: &mut Budget) -> Vec<String> {
+        let mut lines = vec![];
+        let mut pending = entries.iter()
+            .filter(|entry| matches!(entry.kind, FolderListingType::Folder))
+            .map(|entry| (root.join(&entry.name), 1))
+            .collect::<VecDeque<_>>();
+
+        while let Some((dir, depth)) = pending.pop_front() {
+            let children = fs::read_dir(&dir).into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+                .map(|entry| {
+                    let kind = if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                        FolderListingType::Folder
+                    } else {
+                        FolderListingType::File
+                    };
+                    self.entry(&entry.path(), entry.file_name().to_string_lossy().into_owned(), kind)
+                })
+                .collect::<Vec<_>>();
+
+            let (folders, files): (Vec<_>, Vec<_>) = children.iter()
+                .partition(|entry| matches!(entry.kind, FolderListingType::Folder));
+
+            let relative = dir.strip_prefix(root).unwrap_or(&dir);
+            let line = format!("{}{}/ {} files{}, {} folders, {}",
+                "  ".repeat(depth - 1),
+                relative.display(),
+                files.len(),
+                describe_mime_types(&files),
+                folders.len(),
+                format_size(files.iter().map(|entry| entry.size).sum()));
+
+            if !budget.take(&line) {
+                lines.push(String::from("... more folders"));
+                break;
+            }
+            lines.push(line);
+
+            if depth < CONTEXT_TREE_DEPTH {
+                pending.extend(folders.iter().map(|entry| (dir.join(&entry.name), depth + 1)));
+            }
+        }
+
+        lines
+    }
+}
+
+/// A line per entry until the budget runs out, the rest are summed up by type
+fn describe_entries(entries: &[Entry], budget: &mut Budget) -> Vec<String> {
+    let mut lines = vec![];
+
+    for (i, entry) in entries.iter().enumerate() {
+        let modified = entry.modified
+            .map(|modified| DateTime::<Local>::from(modified).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| String::from("unknown"));
+
+        let line = match entry.kind {
+            FolderListingType::Folder => format!("{}/ (folder, modified {modified})", entry.name),
+            _ => format!("{} ({}, {}, modified {modified})",
+                entry.name, entry.mime, format_size(entry.size))
+        };
+
+        if !budget.take(&line) {
+            let rest = entries[i..].iter().collect::<Vec<_>>();
+            lines.push(format!("... and {} more{}", rest.len(), describe_mime_types(&rest)));
+            break;
+        }
+        lines.push(line);
+    }
+
+    if entries.is_empty() {
+        lines.push(String::from("The folder is empty"));
+    }
+
+    lines
+}
+
+/// The most common types among `entries`, like " (12 image/png, 3 text/plain)"
+fn describe_mime_types(entries: &[&Entry]) -> String {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for entry in entries {
+        *counts.entry(&entry.mime).or_default() += 1;
+    }
+
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1));
+
+    if counts.is_empty() {
+        return String::new();
+    }
+
+    format!(" ({})", counts.iter()
+        .take(3)
+        .map(|(mime, count)| format!("{count} {mime}"))
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = [ "B", "KB", "MB", "GB", "TB" ];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, mime: &str) -> Entry {
+        Entry {
+            name: name.into(),
+            kind: FolderListingType::File,
+            mime: mime.into(),
+            size: 2048,
+            modified: None
+        }
+    }
+
+    #[test]
+    fn context_truncates_listing() {
+        let entries = (0..100)
+            .map(|i| entry(&format!("photo-{i}.jpg"), "image/jpeg"))
+            .chain(Some(entry("notes.txt", "text/plain")))
+            .collect::<Vec<_>>();
+
+        let mut budget = Budget::new(100);
+        let lines = describe_entries(&entries, &mut budget);
+
+        assert_eq!(lines[0], "photo-0.jpg (image/jpeg, 2.0 KB, modified unknown)");
+        assert!(lines.len() < entries.len());
+        assert!(lines.last().unwrap().starts_with("... and "));
+        assert!(lines.last().unwrap().ends_with("image/jpeg, 1 text/plain)"));
+    }
+
+    #[test]
+    fn context_format_size() {
+        assert_eq!(format_size(12), "12 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+}
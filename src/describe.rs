@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashSet;
+use std::sync::{Arc,Mutex};
+use serde::Deserialize;
+use tokio::runtime::Handle;
+use wry::application::event_loop::EventLoopProxy;
+use crate::{run_prompt,Action,Folder,Store,Suggestions,Trash,UserEvent};
+
+/// Only this many names are sent to describe a folder, it's enough to tell what it's for
+const DESCRIBE_MAX_NAMES: usize = 100;
+
+/// Asks the assistant what a folder is for and what might be done with it, so suggestions can be
+/// made before anything has been asked. Descriptions are cached until the folder's listing
+/// changes.
+#[derive(Clone)]
+pub struct Describer {
+    rt: Handle,
+    proxy: EventLoopProxy<UserEvent>,
+    /// Folders being described right now, so a folder is only described once at a time
+    pending: Arc<Mutex<HashSet<String>>>
+}
+
+#[derive(Deserialize)]
+struct DescribedAction {
+    question: String,
+    description: Option<String>,
+    code: String
+}
+
+impl Describer {
+    pub fn new(rt: Handle, proxy: EventLoopProxy<UserEvent>) -> Self {
+        Self {
+            rt,
+            proxy,
+            pending: Arc::new(Mutex::new(HashSet::new()))
+        }
+    }
+
+    /// Describes the folder in the background unless its description is up to date, sending
+    /// the new suggestions when it's done
+    pub fn describe(&self, folder: &Folder) {
+        if folder.files.is_empty() || folder.path == Trash::home_dir().files {
+            return;
+        }
+
+        let store = Store::new();
+        let listing = listing_hash(folder);
+        let fresh = store.get_description(&folder.path)
+            .map(|(described, _)| described == listing)
+            .unwrap_or(false);
+
+        let account = match store.get_settings().account {
+            Some(account) if !fresh => account,
+            _ => return
+        };
+
+        if !self.pending.lock().unwrap().insert(listing.clone()) {
+            return;
+        }
+
+        let input = folder.files.iter()
+            .take(DESCRIBE_MAX_NAMES)
+            .map(|listing| format!(r#""{}""#, listing.name))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let path = folder.path.clone();
+        let proxy = self.proxy.clone();
+        let pending = self.pending.clone();
+
+        self.rt.spawn(async move {
+            match run_prompt("describe.pr", &input, &account).await {
+                Ok(result) => match parse(&result) {
+                    Some(description) => {
+                        let store = Store::new();
+                        store.set_description(&path, &listing, &description);
+                        proxy.send_event(UserEvent::UpdateSuggestions {
+                            description: store.get_suggestions(&path)
+                        });
+                    },
+                    None => log::warn!("Couldn't describe {}: {result}", path.display())
+                },
+                Err(e) => log::warn!("Couldn't describe {}: {e}", path.display())
+            }
+
+            pending.lock().unwrap().remove(&listing);
+        });
+    }
+}
+
+/// Identifies a listing by the folder's path and the names in it, so a description is made
+/// again when files are added, removed or renamed
+fn listing_hash(folder: &Folder) -> String {
+    let mut names = folder.files.iter().map(|listing| listing.name.as_str()).collect::<Vec<_>>();
+    names.sort();
+
+    let listing = format!("{}\n{}", folder.path.display(), names.join("\n"));
+    format!("{:x}", md5::compute(listing))
+}
+
+/// The purpose on the first line and the actions as JSON after it
+fn parse(result: &str) -> Option<Suggestions> {
+    let (purpose, actions) = result.strip_prefix("SUCCESS:")?.split_once('\n')?;
+    let actions = serde_json::from_str::<Vec<DescribedAction>>(actions.trim()).ok()?;
+
+    Some(Suggestions {
+        purpose: purpose.trim().to_string(),
+        actions: actions.into_iter()
+            .map(|action| Action {
+                code: action.code,
+                description: action.description,
+                question: action.question,
+                ..Default::default()
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_parse() {
+        let result = "SUCCESS:This directory is likely used to hold notes\n\
+            [{ \"question\": \"Convert notes to html\", \"description\": \"Convert to html\", \
+            \"code\": \"pandoc a.md -o a.html\" }]\n";
+
+        let description = parse(result).unwrap();
+        assert_eq!(description.purpose, "This directory is likely used to hold notes");
+        assert_eq!(description.actions.len(), 1);
+        assert_eq!(description.actions[0].code, "pandoc a.md -o a.html");
+
+        assert!(parse("FAILURE:I've gone nuts").is_none());
+    }
+}
@@ -20,11 +20,14 @@ use std::thread::{self,JoinHandle};
 use wry::application::event_loop::{EventLoopProxy};
 use fs_extra::{move_items_with_progress, copy_items_with_progress, dir::{CopyOptions,TransitProcessResult}};
 use crate::{FileTransfer,FileTransferKind,FileTransferCmd,FileTransferCmdStart,FileTransferCmdResponse,FileTransferProgress,FileTransferProgressState,UserEvent};
+use crate::journal::Journal;
+use crate::models::Operation;
 use std::collections::VecDeque;
 use log;
 
 pub struct FileTransferService {
     proxy: EventLoopProxy<UserEvent>,
+    journal: Journal,
     queue: Arc<Mutex<VecDeque<FileTransferCmdStart>>>,
     running: Arc<Mutex<Option<Transfer>>>
 }
@@ -32,9 +35,10 @@ pub struct FileTransferService {
 struct Transfer(Sender<FileTransferCmdResponse>);
 
 impl FileTransferService {
-    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
+    pub fn new(proxy: EventLoopProxy<UserEvent>, journal: Journal) -> Self {
         Self {
             proxy,
+            journal,
             queue: Arc::new(Mutex::new(VecDeque::new())),
             running: Arc::new(Mutex::new(None))
         }
@@ -62,6 +66,7 @@ impl FileTransferService {
         }
 
         let proxy = self.proxy.clone();
+        let journal = self.journal.clone();
         let queue = self.queue.clone();
         let running = self.running.clone();
 
@@ -73,7 +78,7 @@ impl FileTransferService {
                     Some(cmd) => {
                         let proxy = proxy.clone();
                         let (sender, receiver) = mpsc::channel::<FileTransferCmdResponse>();
-                        let handle = spawn_file_transfer(cmd, receiver, proxy);
+                        let handle = spawn_file_transfer(cmd, receiver, proxy, journal.clone());
                         running.lock().unwrap().replace(Transfer(sender));
                         handle.join().unwrap();
                         log::trace!("File transfer finished");
@@ -91,16 +96,17 @@ impl FileTransferService {
 fn spawn_file_transfer(
     cmd: FileTransferCmdStart,
     rec: Receiver<FileTransferCmdResponse>,
-    proxy: EventLoopProxy<UserEvent>) -> JoinHandle<()>
+    proxy: EventLoopProxy<UserEvent>,
+    journal: Journal) -> JoinHandle<()>
 {
     let options = CopyOptions::new();
 
     thread::spawn(move || {
-        let from: Vec<_> = cmd.names.into_iter().map(|name| cmd.parent.join(name)).collect();
+        let from: Vec<_> = cmd.names.iter().map(|name| cmd.parent.join(name)).collect();
         let mut file_transfer = FileTransfer {
             state: Default::default(),
             progress: Default::default(),
-            from: cmd.parent,
+            from: cmd.parent.clone(),
             to: cmd.to.clone(),
             kind: cmd.kind
         };
@@ -121,6 +127,18 @@ fn spawn_file_transfer(
             log::error!("Error transfering files: {e:?}");
         }
 
+        // Items can be skipped, or the transfer aborted or failed part way through, so only the
+        // ones that actually made it are journaled
+        if matches!(cmd.kind, FileTransferKind::Cut) {
+            let names = cmd.names.into_iter()
+                .filter(|name| !cmd.parent.join(name).exists() && cmd.to.join(name).exists())
+                .collect::<Vec<_>>();
+
+            if !names.is_empty() {
+                journal.record(Operation::Move { parent: cmd.parent, names, to: cmd.to });
+            }
+        }
+
         file_transfer.state = FileTransferProgressState::Finished;
         proxy.send_event(UserEvent::FileTransferProgress(file_transfer)).unwrap();
     })
@@ -15,10 +15,12 @@
  */
 
 use serde::{Deserialize,Serialize};
-use crate::{ConversationItem,Folder,FileMetadata,Suggestions,Options,Settings};
+use derive_more::Display;
+use crate::{ActionArgument,ActionImport,ActionScope,ConversationItem,ScriptOutputLine,ScriptPreview,ScriptSafety,Folder,FileMetadata,Suggestions,Options,Settings,TrashExpiry,TrashUsage,TrashRestoreConflict,UsageReport};
 use fs_extra::TransitProcess;
 use fs_extra::dir::{TransitState,TransitProcessResult};
 
+use std::collections::HashMap;
 use std::path::{PathBuf};
 use url::Url;
 
@@ -52,6 +54,12 @@ pub enum Cmd {
         to: String,
         options: Options
     },
+    Undo {
+        options: Options
+    },
+    Redo {
+        options: Options
+    },
     Settings {
         settings: Settings
     },
@@ -59,22 +67,73 @@ pub enum Cmd {
         files: Vec<String>
     },
     Trash(TrashCmd),
+    Suggestion(SuggestionCmd),
+    /// Shares saved actions through bundle files
+    Actions(ActionsCmd),
     Communicate {
-        message: String
+        message: String,
+        /// Names of the files selected in the current folder
+        #[serde(default)]
+        selected: Vec<String>
     },
+    /// Forget the conversation had about the current folder
+    ClearConversation,
+    /// Evaluates the code of `item`, filling in any placeholders in it with the `arguments`
     Evaluate {
         item: ConversationItem,
-        options: Options
+        options: Options,
+        #[serde(default)]
+        arguments: HashMap<String, ActionArgument>
+    },
+    /// Dry run a script to see what it would change before evaluating it for real
+    Preview {
+        item: ConversationItem,
+        #[serde(default)]
+        arguments: HashMap<String, ActionArgument>
+    },
+    /// Kill the script started by `Evaluate` if it's still running
+    CancelScript,
+    /// The tokens used by the AI over the last `days` days, today included, or over every day
+    UsageReport {
+        #[serde(default)]
+        days: Option<u32>
     },
     Window(WindowCmd),
 }
 
+/// Manages the actions suggested for the current folder, which are identified by their code. An
+/// action suggested because of its scope is changed in the folder it was saved in.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionCmd {
+    Delete { code: String },
+    Rename { code: String, description: String },
+    Edit { code: String, new_code: String },
+    Pin { code: String, pinned: bool },
+    Scope { code: String, scope: ActionScope }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionsCmd {
+    /// Exports the actions saved in folders under `prefix`, or every action when there's none
+    Export { to: PathBuf, prefix: Option<PathBuf> },
+    /// Imports the actions in a bundle, replacing the ones they conflict with if `replace` is set
+    Import {
+        from: PathBuf,
+        #[serde(default)]
+        replace: bool
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TrashCmd {
     Put { paths: Vec<PathBuf> },
-    Restore { paths: Vec<String> },
-    Clear { paths: Option<Vec<String>> }
+    Restore { paths: Vec<String>, to: Option<PathBuf> },
+    Resolve { name: String, to: PathBuf, response: FileTransferCmdResponse },
+    Clear { paths: Option<Vec<String>> },
+    Usage {}
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -214,9 +273,42 @@ pub enum UserEvent {
     UpdateSettings {
         settings: Settings
     },
+    ScriptPreview {
+        preview: ScriptPreview
+    },
+    ScriptOutput {
+        line: ScriptOutputLine
+    },
+    UpdateTrashUsage {
+        usage: TrashUsage
+    },
+    TrashExpired {
+        expiry: TrashExpiry
+    },
+    TrashRestoreConflicts {
+        conflicts: Vec<TrashRestoreConflict>
+    },
+    /// An undo or redo that couldn't be done, the operation stays where it was in the journal
+    JournalFailed {
+        error: String
+    },
     NonexistentFolder {
         path: String
     },
+    ActionsExported {
+        to: PathBuf,
+        count: usize
+    },
+    ActionsImported {
+        from: PathBuf,
+        import: ActionImport
+    },
+    ActionsFailed {
+        error: String
+    },
+    UsageReport {
+        report: UsageReport
+    },
     Ai(AiResponse),
 }
 
@@ -236,6 +328,61 @@ pub struct SubscriptionServer {
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "response", rename_all = "camelCase")]
 pub enum AiResponse {
-    Failure(String),
-    Success(String)
+    Success {
+        code: String,
+        safety: ScriptSafety
+    },
+    /// The assistant answered without a script, like when it can't help with the question
+    Refusal {
+        message: String
+    },
+    /// The script the assistant came up with was turned down before being shown, or couldn't be
+    /// evaluated as asked
+    Rejected {
+        message: String
+    },
+    /// The key for the account wasn't accepted
+    Auth {
+        message: String
+    },
+    #[serde(rename_all = "camelCase")]
+    Quota {
+        message: String,
+        backoff_secs: Option<u64>
+    },
+    #[serde(rename_all = "camelCase")]
+    Network {
+        message: String,
+        backoff_secs: Option<u64>
+    },
+    /// The assistant's answer wasn't in the form the prompt asked for
+    ParseFailure {
+        message: String,
+        output: String
+    }
+}
+
+/// Why a prompt couldn't be run. `backoff_secs` is a default wait before trying again rather than
+/// one the server asked for, it's `None` when trying again won't work until something changes, like
+/// a quota being topped up.
+#[derive(Clone, Debug, Display, PartialEq)]
+pub enum AiError {
+    #[display(fmt = "{}", message)]
+    Auth { message: String },
+    #[display(fmt = "{}", message)]
+    Quota { message: String, backoff_secs: Option<u64> },
+    #[display(fmt = "{}", message)]
+    Network { message: String, backoff_secs: Option<u64> }
+}
+
+impl std::error::Error for AiError {}
+
+impl From<AiError> for AiResponse {
+    fn from(e: AiError) -> Self {
+        match e {
+            AiError::Auth { message } => AiResponse::Auth { message },
+            AiError::Quota { message, backoff_secs } => AiResponse::Quota { message, backoff_secs },
+            AiError::Network { message, backoff_secs } => AiResponse::Network { message, backoff_secs }
+        }
+    }
 }
@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+use std::path::PathBuf;
+use fs_extra::{move_items,dir::CopyOptions};
+use crate::models::Operation;
+use crate::store::Store;
+use crate::trash::Trash;
+
+/// Undo and redo for file operations. The journal itself lives in the `Store` so it survives
+/// restarts.
+#[derive(Clone)]
+pub struct Journal {
+    store: Store,
+    trash: Trash
+}
+
+impl Journal {
+    pub fn new(store: Store) -> Self {
+        Self {
+            store,
+            trash: Trash::new()
+        }
+    }
+
+    pub fn record(&self, operation: Operation) {
+        log::trace!("Journal record -> {operation:#?}");
+        self.store.push_operation(&operation);
+    }
+
+    /// Inverts the most recent operation, returning false when there's nothing left to undo and
+    /// why when it couldn't be undone
+    pub fn undo(&self) -> Result<bool, String> {
+        let Some((id, operation)) = self.store.get_operation(false) else {
+            return Ok(false);
+        };
+
+        log::trace!("Journal undo -> {operation:#?}");
+
+        match &operation {
+            Operation::Rename { from, to } => {
+                if from.exists() {
+                    return Err(format!("Can't undo the rename, {} already exists", from.display()));
+                }
+                fs::rename(to, from)
+                    .map_err(|e| format!("Can't undo the rename of {}: {e}", from.display()))?;
+            },
+            Operation::Trash { items } => {
+                let names = items.iter().map(|(_, name)| name.clone()).collect::<Vec<_>>();
+                // Whatever did come back stays restored, the undo only counts once everything has
+                let conflicts = self.trash.restore(&names, None, &Default::default())
+                    .map_err(|e| format!("Can't restore from the trash: {e}"))?;
+
+                if !conflicts.is_empty() {
+                    let paths = conflicts.iter()
+                        .map(|conflict| conflict.to.display().to_string())
+                        .collect::<Vec<_>>();
+                    return Err(format!("Can't restore everything from the trash, {} already exist",
+                        paths.join(", ")));
+                }
+            },
+            Operation::Move { parent, names, to } => {
+                let moved = names.iter().map(|name| to.join(name)).collect::<Vec<_>>();
+
+                move_items(&moved, parent, &CopyOptions::new())
+                    .map_err(|e| format!("Can't undo the move to {}: {e}", to.display()))?;
+            }
+        }
+
+        self.store.set_operation(id, &operation, true);
+        Ok(true)
+    }
+
+    /// Repeats the most recently undone operation, returning false when there's nothing to redo
+    /// and why when it couldn't be redone
+    pub fn redo(&self) -> Result<bool, String> {
+        let Some((id, operation)) = self.store.get_operation(true) else {
+            return Ok(false);
+        };
+
+        log::trace!("Journal redo -> {operation:#?}");
+
+        let operation = match operation {
+            Operation::Rename { from, to } => {
+                if to.exists() {
+                    return Err(format!("Can't redo the rename, {} already exists", to.display()));
+                }
+                fs::rename(&from, &to)
+                    .map_err(|e| format!("Can't redo the rename of {}: {e}", from.display()))?;
+                Operation::Rename { from, to }
+            },
+            Operation::Trash { items } => {
+                // Trashing again can pick different names, which the next undo will need
+                let paths = items.into_iter().map(|(path, _)| path).collect::<Vec<PathBuf>>();
+                let items = self.trash.put(&paths)
+                    .map_err(|e| format!("Can't move to the trash again: {e}"))?;
+                Operation::Trash { items }
+            },
+            Operation::Move { parent, names, to } => {
+                let moved = names.iter().map(|name| parent.join(name)).collect::<Vec<_>>();
+
+                move_items(&moved, &to, &CopyOptions::new())
+                    .map_err(|e| format!("Can't redo the move to {}: {e}", to.display()))?;
+                Operation::Move { parent, names, to }
+            }
+        };
+
+        self.store.set_operation(id, &operation, false);
+        Ok(true)
+    }
+}
@@ -22,9 +22,10 @@ use xdg_mime::{SharedMimeInfo, Guess};
 use wry::application::event_loop::EventLoopProxy;
 use url::Url;
 use tokio::runtime::Runtime;
-use crate::{Icons,Thumbnails,UserEvent,Options,Folder,FolderListing,FolderListingType,FileMetadata,Sort};
+use crate::{Icons,Thumbnails,Trash,UserEvent,Options,Folder,FolderListing,FolderListingType,FileMetadata,Sort};
 use std::ffi::OsStr;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use notify::{RecursiveMode,Watcher,RecommendedWatcher};
 use notify_debouncer_mini::{new_debouncer,Debouncer,DebounceEventResult};
 use std::time::{Duration,SystemTime,UNIX_EPOCH};
@@ -99,6 +100,11 @@ impl Location {
         folder
     }
 
+    /// Lists a folder with the current options without navigating to it
+    pub fn list(&self, path: &Path) -> Folder {
+        Self::get_folder(path, &self.current_options(), &self.mime_db, &self.thumbnails, &self.icons)
+    }
+
     pub fn back(&self, options: &Options) {
         let path = self.current.lock().unwrap().path.clone();
 
@@ -151,14 +157,14 @@ impl Location {
                     home.join(&s[2..])
                 },
                 s @ _ if s == "Trash" || s == "trash" => {
-                    dirs::data_dir().unwrap().join("Trash").join("files")
+                    Trash::home_dir().files
                 },
                 s @ _ => {
                     PathBuf::from(s)
                 }
             },
             "trash" => {
-                dirs::data_dir().unwrap().join("Trash").join("files")
+                Trash::home_dir().files
             },
             _ => {
                 return None
@@ -194,10 +200,28 @@ impl Location {
         thumbnails: &Thumbnails,
         icons: &Icons) -> Folder
     {
+        // The home trash stands in for every trash directory, items trashed on other volumes are
+        // listed right alongside it
+        let (sources, trashed) = if path == Trash::home_dir().files {
+            let trash = Trash::new();
+            (
+                trash.dirs().into_iter().map(|dir| dir.files).collect(),
+                trash.list().into_iter().map(|item| (item.path.clone(), item)).collect()
+            )
+        } else {
+            (vec![path.to_path_buf()], HashMap::new())
+        };
+
+        // Trashed items are sorted by when they were deleted rather than last modified
+        let date = |entry: &DirEntry| match trashed.get(&entry.path()) {
+            Some(item) => item.deletion_date.map(SystemTime::from),
+            None => entry.metadata().ok().map(|m| m.modified().ok()).flatten()
+        };
+
         let files = if path.is_dir() {
-            let (mut folders, mut files) = fs::read_dir(&path)
-                .unwrap()
-                .into_iter()
+            let (mut folders, mut files) = sources.iter()
+                .filter_map(|dir| fs::read_dir(dir).ok())
+                .flatten()
                 .filter_map(|entry| entry.ok())
                 .filter_map(|entry| {
                     let name = entry.file_name().to_string_lossy().into_owned();
@@ -226,6 +250,7 @@ impl Location {
                     match options.sort {
                         Sort::AToZ => a_name.cmp(&b_name),
                         Sort::ZToA => b_name.cmp(&a_name),
+                        Sort::Date if !trashed.is_empty() => date(a).cmp(&date(b)),
                         Sort::Date => Ordering::Equal
                     }
                 });
@@ -236,6 +261,7 @@ impl Location {
                     match options.sort {
                         Sort::AToZ => a_name.cmp(&b_name),
                         Sort::ZToA => b_name.cmp(&a_name),
+                        Sort::Date if !trashed.is_empty() => date(a).cmp(&date(b)),
                         Sort::Date => Ordering::Equal
                     }
                 });
@@ -250,10 +276,7 @@ impl Location {
                         Sort::AToZ => a_name.cmp(&b_name),
                         Sort::ZToA => b_name.cmp(&a_name),
                         Sort::Date => {
-                            let a_modified = a.metadata().ok().map(|m| m.modified().ok()).flatten();
-                            let b_modified = b.metadata().ok().map(|m| m.modified().ok()).flatten();
-
-                            match (a_modified, b_modified) {
+                            match (date(a), date(b)) {
                                 (Some(a), Some(b)) => a.cmp(&b),
                                 _ => Ordering::Equal
                             }
@@ -268,6 +291,7 @@ impl Location {
             files.into_iter()
                 .map(|entry| {
                     let name = entry.file_name().to_string_lossy().into_owned();
+                    let path = entry.path();
                     let mut icon_url = |entry: DirEntry| {
                         if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
                             Some(get_folder_icon_url(&entry.path()))
@@ -292,8 +316,6 @@ impl Location {
 
                     let mut graphic = match (guess.uncertain(), guess.mime_type()) {
                         (false, mime) if mime.type_() == mime::IMAGE => {
-                            let path = entry.path();
-
                             thumbnails.url_from(&path).or_else(|| {
                                 thumbnails.generate(&path);
                                 icon_url(entry)
@@ -306,7 +328,18 @@ impl Location {
                         graphic.set_query(Some(&format!("v={}", cache_mtime)));
                     }
 
-                    FolderListing { name, kind, graphic }
+                    let trashed = trashed.get(&path);
+
+                    FolderListing {
+                        name,
+                        kind,
+                        graphic,
+                        original_path: trashed.and_then(|item| item.original.clone()),
+                        deletion_date: trashed
+                            .and_then(|item| item.deletion_date)
+                            .map(|date| date.to_rfc3339()),
+                        size: trashed.map(|item| item.size)
+                    }
                 })
                 .collect::<Vec<FolderListing>>()
         } else {
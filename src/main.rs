@@ -29,19 +29,40 @@ mod trash;
 mod location;
 mod compress;
 mod send_to;
+mod agent;
+mod bundle;
+mod context;
+mod describe;
+mod journal;
+mod preview;
+mod prompt_tests;
+mod safety;
+mod scope;
+mod script;
+mod shell;
+mod targets;
+mod template;
+mod usage;
 
 use ipc::*;
 use file_transfer::{FileTransferService};
-use models::{Action,Account,AccountDirect,AccountAerome,ConversationItem,Suggestions,Folder,FolderListing,FileMetadata,FolderListingType,Options,Sort,Settings};
+use models::{Action,ActionArgument,ActionImport,ActionScope,Account,AccountDirect,AccountAerome,AccountLocal,ConversationItem,ConversationItemFrom,Operation,ScriptOutputLine,ScriptPreview,ScriptSafety,Suggestions,Folder,FolderListing,FileMetadata,FolderListingType,Options,Sort,Settings,ScriptPolicy,TrashExpiry,TrashUsage,TrashRestoreConflict,UsageReport};
 use icons::Icons;
 use trash::Trash;
-use tokio::{runtime::{Runtime},process::Command};
+use journal::Journal;
+use preview::Preview;
+use agent::{Agent,PromptOutcome};
+use context::FolderContext;
+use describe::Describer;
+use targets::validate_targets;
+use safety::analyze;
+use script::{ScriptRunner,run_preview_sync,sandbox_missing};
+use tokio::runtime::{Handle,Runtime};
 use tokio::io::{BufReader,AsyncBufReadExt,AsyncWriteExt,AsyncReadExt};
-use tokio::task::AbortHandle;
 use std::borrow::Cow;
 use std::fs;
 use std::ffi::OsStr;
-use std::path::{PathBuf,Path};
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::{Arc,Mutex};
 use std::env::current_dir;
@@ -80,6 +101,13 @@ use send_to::SendTo;
 
 fn main() -> wry::Result<()> {
     env_logger::init();
+
+    if std::env::args().any(|arg| arg == "--test-prompts") {
+        let passed = Runtime::new().unwrap()
+            .block_on(prompt_tests::test_prompts(&mut std::io::stdout()));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     constants::install();
 
     let store = Store::new();
@@ -96,7 +124,14 @@ fn main() -> wry::Result<()> {
         thumbnails.clone(),
         icons.clone());
     let rt = Runtime::new().unwrap();
-    let file_transfer = FileTransferService::new(proxy.clone());
+    let journal = Journal::new(store.clone());
+    let file_transfer = FileTransferService::new(proxy.clone(), journal.clone());
+    let scripts = ScriptRunner::new();
+    let agent = Agent::new(location.clone(), thumbnails.clone());
+    let context = FolderContext::new(mime_db);
+    let describer = Describer::new(rt.handle().clone(), proxy.clone());
+
+    spawn_trash_expiry(proxy.clone());
 
     let handler = move |window: &Window, req: String| {
         match serde_json::from_str(req.as_str()).unwrap() {
@@ -147,38 +182,166 @@ fn main() -> wry::Result<()> {
             Cmd::Window(WindowCmd::Close) => {
                 proxy.send_event(UserEvent::CloseWindow);
             },
-            Cmd::Communicate { message } => {
+            Cmd::Communicate { message, selected } => {
                 let settings = store.get_settings();
 
                 if let Some(account) = settings.account {
-                    communicate(&rt, &message, proxy.clone(), &location.current_folder(), &account);
+                    communicate(
+                        &rt,
+                        &message,
+                        &selected,
+                        proxy.clone(),
+                        &agent,
+                        &context,
+                        &location.current_folder(),
+                        &account);
+                }
+            },
+            Cmd::Suggestion(cmd) => {
+                let current_path = location.current_path();
+                let code = match &cmd {
+                    SuggestionCmd::Delete { code } |
+                    SuggestionCmd::Rename { code, .. } |
+                    SuggestionCmd::Edit { code, .. } |
+                    SuggestionCmd::Pin { code, .. } |
+                    SuggestionCmd::Scope { code, .. } => code
+                };
+                let path = store.suggestion_origin(&current_path, code);
+
+                match cmd {
+                    SuggestionCmd::Delete { code } => store.delete_suggestion(&path, &code),
+                    SuggestionCmd::Rename { code, description } => {
+                        store.rename_suggestion(&path, &code, &description)
+                    },
+                    SuggestionCmd::Edit { code, new_code } => {
+                        store.edit_suggestion(&path, &code, &new_code)
+                    },
+                    SuggestionCmd::Pin { code, pinned } => store.pin_suggestion(&path, &code, pinned),
+                    SuggestionCmd::Scope { code, scope } => store.scope_suggestion(&path, &code, &scope)
                 }
+
+                proxy.send_event(UserEvent::UpdateSuggestions {
+                    description: store.get_suggestions(&current_path)
+                });
+            },
+            Cmd::Actions(ActionsCmd::Export { to, prefix }) => {
+                match bundle::export(&store, &to, prefix.as_deref()) {
+                    Ok(count) => proxy.send_event(UserEvent::ActionsExported { to, count }),
+                    Err(error) => proxy.send_event(UserEvent::ActionsFailed { error })
+                };
             },
-            Cmd::Evaluate { item, options } if item.code.is_some() => {
+            Cmd::Actions(ActionsCmd::Import { from, replace }) => {
+                match bundle::import(&store, &from, replace) {
+                    Ok(import) => {
+                        proxy.send_event(UserEvent::ActionsImported { from, import });
+                        proxy.send_event(UserEvent::UpdateSuggestions {
+                            description: store.get_suggestions(&location.current_path())
+                        });
+                    },
+                    Err(error) => {
+                        proxy.send_event(UserEvent::ActionsFailed { error });
+                    }
+                };
+            },
+            Cmd::UsageReport { days } => {
+                let since = days.map(|days| {
+                    let since = chrono::Local::now() - chrono::Duration::days(days.saturating_sub(1).into());
+                    since.format("%Y-%m-%d").to_string()
+                });
+
+                proxy.send_event(UserEvent::UsageReport {
+                    report: store.get_usage(since.as_deref())
+                });
+            },
+            Cmd::ClearConversation => {
+                store.clear_conversation(&location.current_path());
+            },
+            Cmd::Evaluate { item, options, arguments } if item.code.is_some() => {
+                // Reported as the script's result so the output started for it in the UI goes away
+                let code = match template::fill(item.code.as_ref().unwrap(), &arguments) {
+                    Ok(code) => code,
+                    Err(e) => {
+                        proxy.send_event(UserEvent::UpdateFolder {
+                            folder: location.current_folder(),
+                            script_result: Some(ConversationItem::new(e, None))
+                        });
+                        return;
+                    }
+                };
                 let script = format!("{}\n echo -e {}",
-                    item.code.as_ref().unwrap(),
+                    code,
                     r#""\n""#);
 
                 let current_path = location.current_path();
-                let result = run_script_sync(script, &current_path);
-
-                location.update(&current_path, &options);
+                let origin = store.suggestion_origin(&current_path, item.code.as_ref().unwrap());
+                let policy = store.get_settings().script_policy;
+                let warning = if sandbox_missing(&policy) { UNSANDBOXED_SCRIPT_WARNING } else { "" };
+                let output_proxy = proxy.clone();
+                let proxy = proxy.clone();
+                let location = location.clone();
+                let handle = rt.handle().clone();
 
-                match (&result, item.message) {
-                    (Ok(_), Some(message)) => {
-                        maybe_add_suggestion(
-                            &rt, proxy.clone(), current_path, message, item.code.unwrap());
+                scripts.start(
+                    &rt,
+                    script,
+                    current_path.clone(),
+                    policy,
+                    move |line| {
+                        output_proxy.send_event(UserEvent::ScriptOutput { line });
                     },
-                    _ => {}
+                    move |result| {
+                        location.update(&current_path, &options);
+
+                        if result.is_ok() {
+                            Store::new().use_suggestion(&origin, item.code.as_ref().unwrap());
+                        }
+
+                        match (&result, item.message) {
+                            (Ok(_), Some(message)) => {
+                                maybe_add_suggestion(
+                                    &handle, proxy.clone(), current_path, message, item.code.unwrap());
+                            },
+                            _ => {}
+                        }
+
+                        proxy.send_event(UserEvent::UpdateFolder {
+                            folder: location.current_folder(),
+                            script_result: Some(result
+                                .map(|r| ConversationItem::new(
+                                    format!("{warning}Command finished with result:\n\n{r}"), None))
+                                .unwrap_or_else(|r| ConversationItem::new(
+                                    format!("{warning}Command finished with error:\n\n{r}"), None)))
+                        });
+                    });
+            },
+            Cmd::CancelScript => {
+                if scripts.cancel() {
+                    let path = location.current_path();
+
+                    proxy.send_event(UserEvent::UpdateFolder {
+                        folder: location.update(&path, &location.current_options()),
+                        script_result: Some(ConversationItem::new(
+                            "The script was cancelled".to_string(), None))
+                    });
                 }
+            },
+            Cmd::Preview { item, arguments } if item.code.is_some() => {
+                let current_path = location.current_path();
+                let policy = store.get_settings().script_policy;
+                let proxy = proxy.clone();
 
-                proxy.send_event(UserEvent::UpdateFolder {
-                    folder: location.current_folder(),
-                    script_result: Some(result
-                        .map(|r| ConversationItem::new(
-                            format!("Command finished with result:\n\n{r}"), None))
-                        .unwrap_or_else(|r| ConversationItem::new(
-                            format!("Command finished with error:\n\n{r}"), None)))
+                thread::spawn(move || {
+                    let preview = match template::fill(item.code.as_ref().unwrap(), &arguments) {
+                        Ok(code) => Preview::preview(
+                            &current_path,
+                            |copy| run_preview_sync(code, &current_path, copy, &policy).map_err(|e| e.to_string())),
+                        Err(e) => ScriptPreview {
+                            error: Some(e),
+                            ..Default::default()
+                        }
+                    };
+
+                    proxy.send_event(UserEvent::ScriptPreview { preview });
                 });
             },
             Cmd::Options { options } => {
@@ -207,12 +370,14 @@ fn main() -> wry::Result<()> {
                         let to = folder.path.join(to);
 
                         fs::create_dir(&to).unwrap();
-                        fs::rename(from, to).unwrap();
+                        fs::rename(&from, &to).unwrap();
+                        journal.record(Operation::Rename { from, to });
                     },
                     (Some(FolderListing { kind, .. }), None) => {
                         let from = folder.path.join(from);
                         let to = folder.path.join(to);
-                        fs::rename(from, to).unwrap();
+                        fs::rename(&from, &to).unwrap();
+                        journal.record(Operation::Rename { from, to });
                     },
                     (None, None) if &*from == "New Folder" => {
                         fs::create_dir(folder.path.join(to)).unwrap();
@@ -229,11 +394,45 @@ fn main() -> wry::Result<()> {
 
                 location.update(&folder.path, &options);
             },
+            Cmd::Undo { options } => {
+                match journal.undo() {
+                    Ok(true) => {
+                        let path = location.current_path();
+                        proxy.send_event(UserEvent::UpdateFolder {
+                            folder: location.update(&path, &options),
+                            script_result: None
+                        });
+                    },
+                    Ok(false) => {},
+                    Err(error) => {
+                        log::error!("Could not undo: {error}");
+                        proxy.send_event(UserEvent::JournalFailed { error });
+                    }
+                }
+            },
+            Cmd::Redo { options } => {
+                match journal.redo() {
+                    Ok(true) => {
+                        let path = location.current_path();
+                        proxy.send_event(UserEvent::UpdateFolder {
+                            folder: location.update(&path, &options),
+                            script_result: None
+                        });
+                    },
+                    Ok(false) => {},
+                    Err(error) => {
+                        log::error!("Could not redo: {error}");
+                        proxy.send_event(UserEvent::JournalFailed { error });
+                    }
+                }
+            },
             Cmd::SendTo { files } => {
                 SendTo::email(&location.current_path(), &files);
             },
             Cmd::Settings { settings } => {
                 store.set_account(&settings.account);
+                store.set_trash_retention(&settings.trash_retention);
+                store.set_script_policy(&settings.script_policy);
                 proxy.send_event(UserEvent::UpdateSettings { settings });
             },
             Cmd::Trash(TrashCmd::Put { paths }) => {
@@ -242,13 +441,42 @@ fn main() -> wry::Result<()> {
                     .map(|path| current_path.join(path))
                     .collect::<Vec<_>>();
 
-                trash.put(&trashed);
+                match trash.put(&trashed) {
+                    Ok(trashed) if !trashed.is_empty() => {
+                        journal.record(Operation::Trash { items: trashed });
+                    },
+                    Ok(_) => {},
+                    Err(e) => log::error!("Could not move to the trash: {e:?}")
+                }
             },
-            Cmd::Trash(TrashCmd::Restore { paths }) => {
-                trash.restore(&*paths);
+            Cmd::Trash(TrashCmd::Restore { paths, to }) => {
+                match trash.restore(&*paths, to.as_deref(), &Default::default()) {
+                    Ok(conflicts) if !conflicts.is_empty() => {
+                        proxy.send_event(UserEvent::TrashRestoreConflicts { conflicts });
+                    },
+                    Ok(_) => {},
+                    Err(e) => log::error!("Could not restore from the trash: {e:?}")
+                }
+                proxy.send_event(UserEvent::UpdateTrashUsage { usage: trash.usage() });
+            },
+            Cmd::Trash(TrashCmd::Resolve { name, to, response }) => {
+                match trash.restore_as(&name, &to, &response) {
+                    Ok(Some(conflict)) => {
+                        proxy.send_event(UserEvent::TrashRestoreConflicts { conflicts: vec![conflict] });
+                    },
+                    Ok(None) => {},
+                    Err(e) => log::error!("Could not restore {name} from the trash: {e:?}")
+                }
+                proxy.send_event(UserEvent::UpdateTrashUsage { usage: trash.usage() });
             },
             Cmd::Trash(TrashCmd::Clear { paths }) => {
-                trash.clear(paths.as_ref().map(|p| &**p));
+                if let Err(e) = trash.clear(paths.as_ref().map(|p| &**p)) {
+                    log::error!("Could not clear the trash: {e:?}");
+                }
+                proxy.send_event(UserEvent::UpdateTrashUsage { usage: trash.usage() });
+            },
+            Cmd::Trash(TrashCmd::Usage {}) => {
+                proxy.send_event(UserEvent::UpdateTrashUsage { usage: trash.usage() });
             },
             _ => {}
         }
@@ -350,12 +578,74 @@ fn main() -> wry::Result<()> {
                 webview.evaluate_script(&format!("setSettings({})", &stringified)).unwrap();
             },
 
+            Event::UserEvent(UserEvent::ScriptPreview { preview }) => {
+                let stringified = serde_json::to_string(&preview).unwrap();
+                webview.evaluate_script(&format!("setScriptPreview({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::ScriptOutput { line }) => {
+                let stringified = serde_json::to_string(&line).unwrap();
+                webview.evaluate_script(&format!("appendScriptOutput({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::UpdateTrashUsage { usage }) => {
+                let stringified = serde_json::to_string(&usage).unwrap();
+                webview.evaluate_script(&format!("setTrashUsage({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::TrashExpired { expiry }) => {
+                let stringified = serde_json::to_string(&expiry).unwrap();
+                webview.evaluate_script(&format!("notifyTrashExpired({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::UsageReport { report }) => {
+                let stringified = serde_json::to_string(&report).unwrap();
+                webview.evaluate_script(&format!("setUsageReport({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::ActionsExported { to, count }) => {
+                let stringified = serde_json::to_string(&json!({ "to": to, "count": count })).unwrap();
+                webview.evaluate_script(&format!("notifyActionsExported({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::ActionsImported { from, import }) => {
+                let stringified = serde_json::to_string(&json!({ "from": from, "import": import })).unwrap();
+                webview.evaluate_script(&format!("notifyActionsImported({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::ActionsFailed { error }) => {
+                let stringified = serde_json::to_string(&json!({ "error": error })).unwrap();
+                webview.evaluate_script(&format!("notifyActionsFailed({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::JournalFailed { error }) => {
+                let stringified = serde_json::to_string(&json!({ "error": error })).unwrap();
+                webview.evaluate_script(&format!("notifyJournalFailed({})", &stringified)).unwrap();
+            },
+
+            Event::UserEvent(UserEvent::TrashRestoreConflicts { conflicts }) => {
+                let stringified = serde_json::to_string(&json!({ "conflicts": conflicts })).unwrap();
+                webview.evaluate_script(&format!("setTrashRestoreConflicts({})", &stringified)).unwrap();
+            },
+
             Event::UserEvent(UserEvent::UpdateFolder { folder, script_result }) => {
                 let stringified = serde_json::to_string(&folder).unwrap();
                 let suggestions = Store::new().get_suggestions(&folder.path);
                 let suggestions = serde_json::to_string(&suggestions).unwrap();
 
                 webview.evaluate_script(&format!("setFolder({})", &stringified)).unwrap();
+                describer.describe(&folder);
+
+                let conversation = Store::new().get_conversation(&folder.path);
+                let conversation = serde_json::to_string(&json!({
+                    "path": folder.path,
+                    "items": conversation
+                })).unwrap();
+                webview.evaluate_script(&format!("setConversation({conversation})")).unwrap();
+
+                if script_result.is_some() {
+                    webview.evaluate_script("finishScriptOutput()").unwrap();
+                }
 
                 match script_result {
                     Some(result) if matches!(result.message, Some(_)) => {
@@ -374,17 +664,16 @@ fn main() -> wry::Result<()> {
             },
 
             Event::UserEvent(UserEvent::Ai(response)) => match response {
-                AiResponse::Success(success) => {
-                    let message = "Sure, I can do that. Please review this script before evaluating it:";
-                    let item = ConversationItem::new(message.to_string(), Some(success));
+                AiResponse::Success { code, safety } => {
+                    let mut item = ConversationItem::new(SCRIPT_REVIEW_MESSAGE.to_string(), Some(code));
+                    item.safety = Some(safety);
                     let item = serde_json::to_string(&item).unwrap();
 
                     webview.evaluate_script(&format!("addConversationItem({item})")).unwrap();
                 },
-                AiResponse::Failure(failure) => {
-                    let item = ConversationItem::new(failure, None);
-                    let item = serde_json::to_string(&item).unwrap();
-                    webview.evaluate_script(&format!("addConversationItem({item})")).unwrap();
+                failure => {
+                    let failure = serde_json::to_string(&failure).unwrap();
+                    webview.evaluate_script(&format!("notifyAiFailure({failure})")).unwrap();
                 },
             },
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } |
@@ -410,35 +699,98 @@ fn open_devtools(webview: &WebView) {}
 fn communicate(
     rt: &Runtime,
     message: &str,
+    selected: &[String],
     proxy: EventLoopProxy<UserEvent>,
+    agent: &Agent,
+    context: &FolderContext,
     folder: &Folder,
     account: &Account)
 {
-    let dir = folder.path.to_string_lossy();
-    let files = folder.files.iter()
-        .filter_map(|f| matches!(f.kind, FolderListingType::File).then(|| f.name.to_string()))
-        .collect::<Vec<_>>().join(", ");
-    let folders = folder.files.iter()
-        .filter_map(|f| matches!(f.kind, FolderListingType::Folder).then(|| f.name.to_string()))
-        .collect::<Vec<_>>().join(", ");
-
-    let message = format!(r#"Given these files "{files}" and folders "{folders}" in this directory "{dir}". {message}"#);
-
+    let folder = folder.clone();
+    let path = folder.path.clone();
+    let selected = selected.to_vec();
+    let message = message.to_string();
     let account = account.clone();
+    let agent = agent.clone();
+    let context = context.clone();
     rt.spawn(async move {
-        let result = run_prompt("communicate.pr", &message, &account).await;
-        let (kind, message) = result.split_once(":")
-            .unwrap_or_else(|| ("FAILURE", "I'm sorry I don't understand, can you try again?"));
-
-        proxy.send_event(UserEvent::Ai(match kind {
-            "SUCCESS" => AiResponse::Success(message.to_string()),
-            _ => AiResponse::Failure(message.to_string()),
-        }));
+        let context = context.describe(&folder, &selected);
+        let store = Store::new();
+        let conversation = store.get_conversation(&path);
+        let history = conversation.iter()
+            .skip(conversation.len().saturating_sub(CONVERSATION_HISTORY_LIMIT))
+            .filter_map(|item| match (item.from, &item.message, &item.code) {
+                (_, _, Some(code)) => Some(format!("Final Answer -> ```bash\n{code}\n```")),
+                (ConversationItemFrom::User, Some(message), _) => Some(format!("Question -> {message}")),
+                (ConversationItemFrom::Ai, Some(message), _) => Some(format!("Final Answer -> {message}")),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+
+        let input = [ vec![ context ], history, vec![ format!("Question -> {message}") ] ]
+            .concat()
+            .join("\n");
+
+        // Saved before asking so the question isn't lost when the request fails
+        store.add_conversation_item(&path, &ConversationItem::user(message));
+
+        let result = agent.run(&path, input, |input| {
+            let account = account.clone();
+            async move { run_prompt("conversation.pr", &input, &account).await }
+        }).await;
+
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                proxy.send_event(UserEvent::Ai(e.into()));
+                return;
+            }
+        };
+
+        let names = folder.files.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+        let response = match PromptOutcome::parse(&output) {
+            Some(PromptOutcome::Success(code)) => {
+                let untargeted = if selected.is_empty() {
+                    None
+                } else {
+                    validate_targets(&code, &path, &names, &selected).err()
+                };
+
+                match untargeted {
+                    Some(untargeted) => {
+                        let message = format!("The script I came up with would also touch {}, \
+                            which aren't selected. Can you try again?", untargeted.join(", "));
+                        store.add_conversation_item(&path, &ConversationItem::new(message.clone(), None));
+                        AiResponse::Rejected { message }
+                    },
+                    None => {
+                        let safety = analyze(&code, &path);
+                        let mut item = ConversationItem::new(
+                            SCRIPT_REVIEW_MESSAGE.to_string(), Some(code.clone()));
+                        item.safety = Some(safety.clone());
+                        store.add_conversation_item(&path, &item);
+
+                        AiResponse::Success { code, safety }
+                    }
+                }
+            },
+            Some(PromptOutcome::Failure(message)) => {
+                store.add_conversation_item(&path, &ConversationItem::new(message.clone(), None));
+                AiResponse::Refusal { message }
+            },
+            Some(PromptOutcome::Action(_)) | None => {
+                let message = String::from("I'm sorry I don't understand, can you try again?");
+                store.add_conversation_item(&path, &ConversationItem::new(message.clone(), None));
+                AiResponse::ParseFailure { message, output }
+            }
+        };
+
+        proxy.send_event(UserEvent::Ai(response));
     });
 }
 
 fn maybe_add_suggestion(
-    rt: &Runtime,
+    rt: &Handle,
     proxy: EventLoopProxy<UserEvent>,
     path: PathBuf,
     message: String,
@@ -447,11 +799,18 @@ fn maybe_add_suggestion(
     rt.spawn(async move {
         let store = Store::new();
         if let Some(account) = store.get_settings().account {
-            let description = Some(run_prompt("summary.pr", &message, &account).await);
+            let description = match run_prompt("summary.pr", &message, &account).await {
+                Ok(description) => Some(description),
+                Err(e) => {
+                    log::warn!("Couldn't summarize \"{message}\": {e}");
+                    None
+                }
+            };
             store.add_suggestion(&path, &Action {
                 code,
                 description,
-                question: message
+                question: message,
+                ..Default::default()
             });
             proxy.send_event(UserEvent::UpdateSuggestions {
                 description: store.get_suggestions(&path)
@@ -460,44 +819,50 @@ fn maybe_add_suggestion(
     });
 }
 
-fn run_script_sync(bash_script: String, current_dir: &Path) -> Result<String, String> {
-    Runtime::new()
-        .unwrap()
-        .block_on(async move {  run_script(bash_script, current_dir).await })
-}
+/// Purges the trash according to the retention policy in the settings, once at startup and then
+/// every hour after that
+fn spawn_trash_expiry(proxy: EventLoopProxy<UserEvent>) {
+    thread::spawn(move || {
+        let trash = Trash::new();
 
-async fn run_script(bash_script: String, current_dir: &Path) -> Result<String, String> {
-    let mut cmd = Command::new("/bin/bash")
-        .args(&[ "-c", &bash_script ])
-        .current_dir(current_dir)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .unwrap();
-
-    if cmd.status.success() {
-        Ok(String::from_utf8(cmd.stdout).unwrap())
-    } else {
-        Err(String::from_utf8(cmd.stderr).unwrap())
-    }
-}
+        loop {
+            let retention = Store::new().get_settings().trash_retention;
+            let expiry = trash.expire(&retention);
 
-async fn run_prompt(prompt_path: &str, input: &str, account: &Account) -> String {
-    let prompts_dir = dirs::data_local_dir()
-        .map(|data_dir| data_dir.join(constants::APP_NAME).join("prompts"))
-        .expect("Could not find the apps data directory");
+            if !expiry.items.is_empty() {
+                proxy.send_event(UserEvent::TrashExpired { expiry });
+            }
 
+            thread::sleep(TRASH_EXPIRY_INTERVAL);
+        }
+    });
+}
+
+/// Runs a prompt with `input` appended to it, returning what the prompt printed
+async fn run_prompt(prompt_path: &str, input: &str, account: &Account) -> Result<String, AiError> {
     let (api_key, api_proxy) = match account {
         Account::Direct(key) => (Some(key.0.clone()), None),
         Account::Aerome(AccountAerome { key, .. }) => (
             Some(key.clone()),
             Some(constants::SUBSCRIPTIONS_SERVER_URL.to_owned())
         ),
+        Account::Local(AccountLocal { base_url, key, .. }) => (
+            Some(key.clone()),
+            Some(base_url.clone())
+        ),
     };
 
+    // Prompts are read from disk on every call, so changes to them and to the overrides apply
+    // without restarting. That stands in for `watch`, which re-evaluates a prompt each time it
+    // changes and never returns, so it only suits running a prompt from the command line.
+    let path = match account {
+        Account::Local(AccountLocal { model, .. }) => local_prompt_path(prompt_path, model),
+        _ => constants::prompt_path(prompt_path)
+    };
+
+    let prompt = fs::read_to_string(&path).unwrap_or_default();
     let args = PromptArgs {
-        path: prompts_dir.join(prompt_path),
+        path,
         quiet: false,
         api_key,
         api_proxy,
@@ -509,54 +874,170 @@ async fn run_prompt(prompt_path: &str, input: &str, account: &Account) -> String
     let out = PromptOut(Vec::new());
     let result = evaluate(args, out).await;
 
-    match result {
-        Ok(out) => String::from_utf8(out.0).unwrap(),
-        Err(e) => {
-            let message = match account {
-                Account::Direct(_) => match e {
-                    EvaluateError::ChatError(ChatError::NetworkError(e)) => {
-                        format!("{}", e.without_url())
-                    },
-                    EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 401 => {
-                        String::from("Invalid API key")
-                    },
-                    EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 429 => {
-                        String::from(
-                            "You've exceeded your quota or Open AI's servers are overloaded")
-                    },
-                    EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 500 => {
-                        String::from("Open AI's server experienced an internal error")
-                    },
-                    _ => {
-                        eprintln!("{e:#?}");
-                        String::from("The AI assistant failed with an unknown error, sorry!")
-                    }
-                },
-                Account::Aerome(_) => match e {
-                    EvaluateError::ChatError(ChatError::NetworkError(e)) => {
-                        format!("{}", e.without_url())
-                    },
-                    EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 401 => {
-                        String::from("Invalid API key")
-                    },
-                    EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 429 => {
-                        String::from("\
-                            You've exceeded your monthly quota. You can buy more credits in the \
-                            account page.")
-                    },
-                    _ => {
-                        eprintln!("{e:#?}");
-                        String::from("The AI assistant failed with an unknown error, sorry!")
-                    }
-                }
-            };
+    let output = result
+        .map(|out| String::from_utf8(out.0).unwrap())
+        .map_err(|e| ai_error(e, account))?;
+
+    Store::new().record_usage(
+        prompt_path,
+        &usage::account_label(account),
+        &usage::prompt_model(&prompt).unwrap_or_else(|| usage::DEFAULT_PROMPT_MODEL.to_string()),
+        usage::estimate_tokens(usage::prompt_body(&prompt)) + usage::estimate_tokens(input),
+        usage::estimate_tokens(&output),
+        true);
+
+    Ok(output)
+}
+
+/// What went wrong running a prompt for `account`, in terms the user can act on
+fn ai_error(e: EvaluateError, account: &Account) -> AiError {
+    let auth = |message: &str| AiError::Auth { message: message.to_string() };
+    let network = |message: String, backoff_secs| AiError::Network { message, backoff_secs };
+
+    match account {
+        Account::Direct(_) => match e {
+            EvaluateError::ChatError(ChatError::NetworkError(e)) => {
+                network(format!("{}", e.without_url()), Some(AI_BACKOFF_SECS))
+            },
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 401 => {
+                auth("Invalid API key")
+            },
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 429 => AiError::Quota {
+                message: String::from("You've exceeded your quota or Open AI's servers are overloaded"),
+                backoff_secs: Some(AI_RATE_LIMIT_BACKOFF_SECS)
+            },
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 500 => {
+                network(String::from("Open AI's server experienced an internal error"),
+                    Some(AI_BACKOFF_SECS))
+            },
+            _ => {
+                log::error!("The AI assistant failed with an unknown error: {e:?}");
+                network(String::from("The AI assistant failed with an unknown error, sorry!"), None)
+            }
+        },
+        Account::Local(AccountLocal { base_url, model, .. }) => match e {
+            // The server has to be started before trying again will help
+            EvaluateError::ChatError(ChatError::NetworkError(e)) => network(
+                format!("The local model couldn't be reached at {base_url}: {}", e.without_url()),
+                None),
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 401 => {
+                auth("The local model's server didn't accept the key")
+            },
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 404 => {
+                network(format!("The local model's server doesn't have a model called {model}"), None)
+            },
+            _ => {
+                log::error!("The local model failed with an unknown error: {e:?}");
+                network(String::from("The local model failed with an unknown error, sorry!"), None)
+            }
+        },
+        Account::Aerome(_) => match e {
+            EvaluateError::ChatError(ChatError::NetworkError(e)) => {
+                network(format!("{}", e.without_url()), Some(AI_BACKOFF_SECS))
+            },
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 401 => {
+                auth("Invalid API key")
+            },
+            EvaluateError::ChatError(ChatError::OpenAIError(e)) if e.status == 429 => AiError::Quota {
+                message: String::from("\
+                    You've exceeded your monthly quota. You can buy more credits in the \
+                    account page."),
+                backoff_secs: None
+            },
+            _ => {
+                log::error!("The AI assistant failed with an unknown error: {e:?}");
+                network(String::from("The AI assistant failed with an unknown error, sorry!"), None)
+            }
+        }
+    }
+}
 
-            format!("FAILURE: {message}")
+/// Local servers have to be told which model to use, unlike OpenAI where the prompts pick one.
+/// The prompts are copied with the model added to the options of each of them. An override made
+/// for the model in `local/<model>` of the overrides is used as is, any other override gets the
+/// model added like the installed prompts do.
+fn local_prompt_path(prompt_path: &str, model: &str) -> PathBuf {
+    let model_dir = PathBuf::from("local").join(model.replace('/', "_"));
+    let overridden = constants::prompt_overrides_dir().join(&model_dir).join(prompt_path);
+
+    if overridden.exists() {
+        return overridden;
+    }
+
+    let dir = constants::prompts_dir().join(&model_dir);
+    let path = dir.join(prompt_path);
+
+    if let Ok(prompt) = fs::read_to_string(constants::prompt_path(prompt_path)) {
+        let prompt = with_model(&prompt, model);
+
+        // Only written when the prompt or the model changed, it's read on every request
+        if fs::read_to_string(&path).ok().as_ref() != Some(&prompt) {
+            let _ = fs::create_dir_all(&dir);
+            let _ = fs::write(&path, prompt);
         }
     }
 
+    path
 }
 
+/// Sets the model in the options of every prompt in a prompt file. A prompt starts with its name
+/// at the start of a line, followed by its indented options and then its body between a `{` and a
+/// `}` at the start of a line.
+fn with_model(prompt: &str, model: &str) -> String {
+    let lines = prompt.lines().collect::<Vec<_>>();
+    let mut output = vec![];
+    let mut in_options = false;
+    let mut in_body = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let indented = trimmed.len() < line.len();
+
+        if in_body {
+            in_body = *line != "}";
+        } else if in_options {
+            if *line == "{" {
+                in_options = false;
+                in_body = true;
+            } else if indented && trimmed.starts_with("model:") {
+                continue;
+            }
+        } else if line.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            output.push(line.to_string());
+
+            let next = lines.get(i + 1).copied().unwrap_or_default();
+            let indent = &next[..next.len() - next.trim_start().len()];
+            let indent = if indent.is_empty() { "    " } else { indent };
+            output.push(format!("{indent}model: {model}"));
+
+            in_options = true;
+            continue;
+        }
+
+        output.push(line.to_string());
+    }
+
+    output.join("\n")
+}
+
+const TRASH_EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long to suggest waiting before asking again after a network or server error. It's a fixed
+/// default, the server's Retry-After header isn't passed on to us.
+const AI_BACKOFF_SECS: u64 = 30;
+
+/// How long to suggest waiting before asking again after being rate limited, also a fixed default
+const AI_RATE_LIMIT_BACKOFF_SECS: u64 = 60;
+
+/// How many of the most recent conversation items are sent back to the assistant
+const CONVERSATION_HISTORY_LIMIT: usize = 20;
+
+const SCRIPT_REVIEW_MESSAGE: &str = "Sure, I can do that. Please review this script before evaluating it:";
+
+const UNSANDBOXED_SCRIPT_WARNING: &str = "\
+    Bubblewrap (bwrap) isn't installed, so the script ran without the limits on writes and \
+    network access in the settings.\n\n";
+
 #[derive(Debug)]
 struct PromptOut(Vec<u8>);
 
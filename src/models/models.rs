@@ -26,10 +26,17 @@ pub struct Folder {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FolderListing {
     pub name: String,
     pub kind: FolderListingType,
-    pub graphic: Option<Url>
+    pub graphic: Option<Url>,
+    /// Where a trashed item was deleted from. This and the fields below are only set when
+    /// listing the trash.
+    pub original_path: Option<PathBuf>,
+    /// RFC 3339 timestamp of when the item was trashed
+    pub deletion_date: Option<String>,
+    pub size: Option<u64>
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -39,6 +46,34 @@ pub enum FolderListingType {
     Link
 }
 
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashUsage {
+    pub total: u64,
+    pub items: Vec<TrashItemUsage>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashItemUsage {
+    pub name: String,
+    pub size: u64
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashExpiry {
+    pub freed: u64,
+    pub items: Vec<TrashItemUsage>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashRestoreConflict {
+    pub name: String,
+    pub to: PathBuf
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub path: PathBuf,
@@ -59,11 +94,169 @@ pub struct Suggestions {
     pub actions: Vec<Action>
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Action {
     pub code: String,
     pub description: Option<String>,
-    pub question: String
+    pub question: String,
+    /// Pinned actions are suggested before any others
+    #[serde(default)]
+    pub pinned: bool,
+    /// How many times the action has been evaluated
+    #[serde(default)]
+    pub uses: u32,
+    /// The folders the action is suggested in besides the one it was saved in
+    #[serde(default)]
+    pub scope: ActionScope,
+    /// The folder the action was saved in, when it's suggested somewhere else because of its scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<PathBuf>,
+    /// The values the code asks for when it's evaluated, going by the placeholders in it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<ActionParameter>,
+    /// What the code does that's worth reviewing, for the folder it's suggested in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<ScriptSafety>
+}
+
+/// How much the prompts run for an account used, either in a day or over the whole report
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<String>,
+    pub account: String,
+    pub model: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Whether any of the counts were estimated from the text rather than reported by the API
+    #[serde(default)]
+    pub estimated: bool
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct UsageReport {
+    /// Newest day first
+    pub days: Vec<Usage>,
+    /// The totals of each account and model over every day in the report
+    pub accounts: Vec<Usage>
+}
+
+/// Saved actions in a form that can be shared with others or used to seed a new machine
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ActionBundle {
+    pub version: u32,
+    pub actions: Vec<BundledAction>
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BundledAction {
+    /// The folder the action was saved in, paths under the home folder start with "~/" so they
+    /// carry over to another user
+    pub path: PathBuf,
+    pub code: String,
+    pub question: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub scope: ActionScope
+}
+
+/// What importing a bundle did. Actions that differ from one already saved with the same code in
+/// the same folder are conflicts, and are left alone unless the import replaces them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionImport {
+    pub added: usize,
+    pub replaced: usize,
+    pub unchanged: usize,
+    pub conflicts: Vec<ActionConflict>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ActionConflict {
+    pub ours: BundledAction,
+    pub theirs: BundledAction
+}
+
+/// A placeholder in an action's code, written as `{{kind}}` or `{{kind:name}}`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ActionParameter {
+    pub name: String,
+    pub kind: ActionParameterKind
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionParameterKind {
+    /// The selected files
+    Files,
+    /// A folder to put things in
+    Folder,
+    Text,
+    Number
+}
+
+/// What a placeholder is filled in with, the names of the selected files or a single value
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ActionArgument {
+    Many(Vec<String>),
+    One(String)
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "scope", rename_all = "snake_case")]
+pub enum ActionScope {
+    /// Only the folder the action was saved in
+    #[default]
+    Folder,
+    /// The folder the action was saved in and every folder below it
+    Tree,
+    /// Every folder whose path matches the glob, like "~/Pictures/*", where `*` also matches
+    /// across folders
+    Pattern { glob: String },
+    Kind { kind: FolderKind }
+}
+
+/// What a folder holds, going by the files in it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderKind {
+    /// Contains a Cargo.toml
+    Rust,
+    /// Contains a package.json
+    Node,
+    /// Contains a .git folder
+    Git,
+    /// Mostly images
+    Images,
+    /// Mostly audio
+    Music,
+    /// Mostly videos
+    Videos,
+    /// Mostly documents, like PDFs and spreadsheets
+    Documents
+}
+
+/// A file operation recorded in the journal, with enough information to invert it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    Rename {
+        from: PathBuf,
+        to: PathBuf
+    },
+    /// Each trashed path along with the name it was given in the trash
+    Trash {
+        items: Vec<(PathBuf, String)>
+    },
+    Move {
+        parent: PathBuf,
+        names: Vec<String>,
+        to: PathBuf
+    }
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
@@ -78,14 +271,50 @@ pub struct Options {
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Settings {
-    pub account: Option<Account>
+    pub account: Option<Account>,
+    #[serde(default)]
+    pub trash_retention: TrashRetention,
+    #[serde(default)]
+    pub script_policy: ScriptPolicy
+}
+
+/// Limits on the scripts the assistant writes
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptPolicy {
+    pub timeout_secs: u64,
+    pub max_output_bytes: usize,
+    /// Only allow writes to the current folder, needs bubblewrap
+    pub restrict_writes: bool,
+    /// Needs bubblewrap to be turned off
+    pub allow_network: bool
+}
+
+impl Default for ScriptPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 5 * 60,
+            max_output_bytes: 1024 * 1024,
+            restrict_writes: true,
+            allow_network: false
+        }
+    }
+}
+
+/// Limits past which trashed items are purged automatically. Neither is enforced by default.
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashRetention {
+    pub max_age_days: Option<u32>,
+    pub max_size: Option<u64>
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum Account {
     Direct(AccountDirect),
-    Aerome(AccountAerome)
+    Aerome(AccountAerome),
+    Local(AccountLocal)
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
@@ -98,6 +327,18 @@ pub struct AccountAerome {
     pub key: String,
 }
 
+/// An OpenAI compatible server, like llama.cpp or ollama, so requests never leave the machine
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountLocal {
+    /// Where the API lives, like "http://localhost:11434/v1"
+    pub base_url: String,
+    pub model: String,
+    /// Only needed when the server is set up to require one
+    #[serde(default)]
+    pub key: String
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub enum Sort {
     #[default]
@@ -109,12 +350,74 @@ pub enum Sort {
     Date,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// What a script would do to the current folder, paths are relative to it
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptPreview {
+    pub created: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub moved: Vec<ScriptPreviewMove>,
+    pub deleted: Vec<PathBuf>,
+    pub error: Option<String>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptPreviewMove {
+    pub from: PathBuf,
+    pub to: PathBuf
+}
+
+/// What a reviewer should know about a script before evaluating it
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptSafety {
+    pub risk: ScriptRisk,
+    pub findings: Vec<ScriptFinding>
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum ScriptRisk {
+    #[default]
+    Low,
+    Medium,
+    High
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptFinding {
+    pub risk: ScriptRisk,
+    /// The line of the script the command is on, counting from one
+    pub line: usize,
+    pub command: String,
+    pub reason: String
+}
+
+/// A line printed by a running script
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptOutputLine {
+    pub stream: ScriptOutputStream,
+    pub text: String
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptOutputStream {
+    Stdout,
+    Stderr
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ConversationItem {
-    from: ConversationItemFrom,
+    pub from: ConversationItemFrom,
     pub message: Option<String>,
-    pub code: Option<String>
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<ScriptSafety>
 }
 
 impl ConversationItem {
@@ -123,13 +426,23 @@ impl ConversationItem {
             code,
             from: ConversationItemFrom::Ai,
             message: Some(message),
+            safety: None
+        }
+    }
+
+    pub fn user(message: String) -> Self {
+        Self {
+            code: None,
+            from: ConversationItemFrom::User,
+            message: Some(message),
+            safety: None
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
-enum ConversationItemFrom {
+pub enum ConversationItemFrom {
     Ai,
     User
 }
@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+use std::io;
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path,PathBuf};
+use std::time::{SystemTime,UNIX_EPOCH};
+use crate::constants::APP_NAME;
+use crate::models::{ScriptPreview,ScriptPreviewMove};
+
+/// Dry runs a script against a copy of `current_dir` and reports what it would do to the files
+/// in it. `run` is handed the copy and has to make sure the script only ever sees that in place of
+/// `current_dir`, with everything else read only.
+pub struct Preview {}
+
+impl Preview {
+    pub fn preview(
+        current_dir: &Path,
+        run: impl FnOnce(&Path) -> Result<String, String>) -> ScriptPreview
+    {
+        let sandbox = std::env::temp_dir().join(format!(
+            "{APP_NAME}-preview-{}-{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()));
+
+        let mut limits = CopyLimits {
+            skip: sandbox.clone(),
+            bytes: PREVIEW_MAX_BYTES,
+            entries: PREVIEW_MAX_ENTRIES
+        };
+        let result = copy_tree(current_dir, &sandbox, &mut limits, 0)
+            .map_err(|e| format!("Couldn't prepare a copy of the folder to preview in: {e}"))
+            .map(|_| {
+                let before = snapshot(&sandbox);
+                let result = run(&sandbox);
+                let after = snapshot(&sandbox);
+
+                diff(&before, &after, result.err())
+            });
+
+        let _ = fs::remove_dir_all(&sandbox);
+
+        result.unwrap_or_else(|error| ScriptPreview {
+            error: Some(error),
+            ..Default::default()
+        })
+    }
+}
+
+struct CopyLimits {
+    /// The copy itself, which ends up inside `current_dir` when previewing one of its parents
+    skip: PathBuf,
+    bytes: u64,
+    entries: usize
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    is_dir: bool,
+    size: u64,
+    mtime: i64,
+    inode: u64
+}
+
+type Snapshot = BTreeMap<PathBuf, Entry>;
+
+/// Copies `from` into `to`. Once the byte budget is spent, files only keep their name and size so
+/// previewing a folder of huge files doesn't mean duplicating all of them. Folders that are too
+/// deep or have too many entries aren't previewed at all.
+fn copy_tree(from: &Path, to: &Path, limits: &mut CopyLimits, depth: usize) -> io::Result<()> {
+    if depth > PREVIEW_MAX_DEPTH {
+        return Err(io::Error::other(format!("it's nested more than {PREVIEW_MAX_DEPTH} folders deep")));
+    }
+
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+
+        if entry.path() == limits.skip {
+            continue;
+        }
+
+        if limits.entries == 0 {
+            return Err(io::Error::other(format!("it has more than {PREVIEW_MAX_ENTRIES} files")));
+        }
+        limits.entries -= 1;
+
+        let kind = entry.file_type()?;
+        let target = to.join(entry.file_name());
+
+        if kind.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else if kind.is_dir() {
+            copy_tree(&entry.path(), &target, limits, depth + 1)?;
+        } else {
+            let size = entry.metadata()?.len();
+
+            if size <= limits.bytes {
+                fs::copy(entry.path(), &target)?;
+                limits.bytes -= size;
+            } else {
+                fs::File::create(&target)?.set_len(size)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn snapshot(root: &Path) -> Snapshot {
+    fn walk(root: &Path, dir: &Path, snapshot: &mut Snapshot) {
+        for entry in fs::read_dir(dir).into_iter().flatten().filter_map(|e| e.ok()) {
+            let Ok(meta) = fs::symlink_metadata(entry.path()) else {
+                continue;
+            };
+
+            if meta.is_dir() {
+                walk(root, &entry.path(), snapshot);
+            }
+
+            snapshot.insert(entry.path().strip_prefix(root).unwrap().to_path_buf(), Entry {
+                is_dir: meta.is_dir(),
+                size: meta.len(),
+                mtime: meta.mtime() * 1_000_000_000 + meta.mtime_nsec(),
+                inode: meta.ino()
+            });
+        }
+    }
+
+    let mut snapshot = Snapshot::new();
+    walk(root, root, &mut snapshot);
+    snapshot
+}
+
+/// Renames keep the inode, so an entry that disappeared and one that appeared with the same inode
+/// are a move rather than a delete and a create
+fn diff(before: &Snapshot, after: &Snapshot, error: Option<String>) -> ScriptPreview {
+    let mut preview = ScriptPreview { error, ..Default::default() };
+    let mut created = after.iter()
+        .filter(|(path, _)| !before.contains_key(*path))
+        .collect::<Vec<_>>();
+
+    for (path, entry) in before {
+        match after.get(path) {
+            Some(now) if now.is_dir || entry == now => {},
+            Some(_) => preview.modified.push(path.clone()),
+            None => match created.iter().position(|(_, now)| now.inode == entry.inode) {
+                Some(i) => preview.moved.push(ScriptPreviewMove {
+                    from: path.clone(),
+                    to: created.remove(i).0.clone()
+                }),
+                None => preview.deleted.push(path.clone())
+            }
+        }
+    }
+
+    preview.created = created.into_iter().map(|(path, _)| path.clone()).collect();
+
+    // Moving a folder moves everything in it as well, only the folder itself is worth reporting
+    let moved = preview.moved.clone();
+    preview.moved.retain(|child| !moved.iter().any(|parent| {
+        parent != child
+            && child.from.strip_prefix(&parent.from).ok() == child.to.strip_prefix(&parent.to).ok()
+            && child.from.starts_with(&parent.from)
+    }));
+
+    // Same goes for deleting and creating whole folders
+    let deleted = preview.deleted.clone();
+    preview.deleted.retain(|path| !deleted.iter().any(|p| p != path && path.starts_with(p)));
+    let created = preview.created.clone();
+    preview.created.retain(|path| !created.iter().any(|p| p != path && path.starts_with(p)));
+
+    preview
+}
+
+const PREVIEW_MAX_BYTES: u64 = 256 * 1024 * 1024;
+const PREVIEW_MAX_ENTRIES: usize = 20_000;
+const PREVIEW_MAX_DEPTH: usize = 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(inode: u64, mtime: i64) -> Entry {
+        Entry { is_dir: false, size: 1, mtime, inode }
+    }
+
+    #[test]
+    fn preview_diff() {
+        let before: Snapshot = vec![
+            (PathBuf::from("kept"), entry(1, 0)),
+            (PathBuf::from("edited"), entry(2, 0)),
+            (PathBuf::from("renamed"), entry(3, 0)),
+            (PathBuf::from("removed"), entry(4, 0)),
+        ].into_iter().collect();
+        let after: Snapshot = vec![
+            (PathBuf::from("kept"), entry(1, 0)),
+            (PathBuf::from("edited"), entry(2, 1)),
+            (PathBuf::from("moved/renamed"), entry(3, 0)),
+            (PathBuf::from("new"), entry(5, 0)),
+        ].into_iter().collect();
+
+        let preview = diff(&before, &after, None);
+
+        assert_eq!(preview.modified, vec![PathBuf::from("edited")]);
+        assert_eq!(preview.deleted, vec![PathBuf::from("removed")]);
+        assert_eq!(preview.created, vec![PathBuf::from("new")]);
+        assert_eq!(preview.moved.len(), 1);
+        assert_eq!(preview.moved[0].to, PathBuf::from("moved/renamed"));
+    }
+
+    #[test]
+    fn preview_copy_limits() {
+        let root = std::env::temp_dir().join(format!("{APP_NAME}-preview-test-{}", std::process::id()));
+        let copy = root.join("copy");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/file"), "x").unwrap();
+
+        // The copy living inside the folder being copied isn't copied into itself
+        let mut limits = CopyLimits { skip: copy.clone(), bytes: 1, entries: 10 };
+        assert!(copy_tree(&root, &copy, &mut limits, 0).is_ok());
+        assert!(copy.join("a/b/file").exists());
+        assert!(!copy.join("copy").exists());
+
+        let mut limits = CopyLimits { skip: copy.clone(), bytes: 1, entries: 2 };
+        assert!(copy_tree(&root.join("a"), &root.join("small"), &mut limits, 0).is_ok());
+        let mut limits = CopyLimits { skip: copy.clone(), bytes: 1, entries: 1 };
+        assert!(copy_tree(&root.join("a"), &root.join("smaller"), &mut limits, 0).is_err());
+        let mut limits = CopyLimits { skip: copy.clone(), bytes: 1, entries: 10 };
+        assert!(copy_tree(&root.join("a"), &root.join("deep"), &mut limits, PREVIEW_MAX_DEPTH).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
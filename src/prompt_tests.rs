@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use prompt::{PromptArgs,evaluate};
+use crate::constants::{PROMPTS,PROMPT_TESTS};
+use crate::PromptOut;
+
+/// Runs the tests of each prompt Aerome ships with, writing whether each one passed to `report`.
+/// The tests answer for the AI, so nothing is sent over the network and no account is needed.
+/// Returns whether they all passed.
+pub async fn test_prompts(report: &mut impl Write) -> bool {
+    let dir = env::temp_dir().join(format!("aerome-prompt-tests-{}", process::id()));
+    let mut passed = true;
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        let _ = writeln!(report, "Couldn't create {}: {e}", dir.display());
+        return false;
+    }
+
+    for (name, test) in PROMPT_TESTS {
+        let prompt = PROMPTS.iter().find(|(prompt_name, _)| prompt_name == name);
+        let result = match prompt {
+            Some((_, prompt)) => run_test(&dir, name, prompt, test).await,
+            None => Err(String::from("there's no prompt for these tests"))
+        };
+
+        match result {
+            Ok(()) => {
+                let _ = writeln!(report, "{name} ... ok");
+            },
+            Err(e) => {
+                let _ = writeln!(report, "{name} ... FAILED\n{e}");
+                passed = false;
+            }
+        }
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+    passed
+}
+
+async fn run_test(dir: &Path, name: &str, prompt: &[u8], test: &[u8]) -> Result<(), String> {
+    let path = dir.join(name);
+    let test_path = path.with_extension("test.yml");
+
+    fs::write(&path, prompt).map_err(|e| e.to_string())?;
+    fs::write(&test_path, test).map_err(|e| e.to_string())?;
+
+    let args = PromptArgs {
+        path,
+        quiet: true,
+        api_key: None,
+        api_proxy: None,
+        append: None,
+        test: Some(test_path),
+        watch: None
+    };
+
+    evaluate(args, PromptOut(Vec::new())).await
+        .map(|_| ())
+        .map_err(|e| format!("{e:#?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_pass_their_tests() {
+        let mut report = vec![];
+        let passed = tokio::runtime::Runtime::new().unwrap()
+            .block_on(test_prompts(&mut report));
+
+        assert!(passed, "{}", String::from_utf8_lossy(&report));
+    }
+}
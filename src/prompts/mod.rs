@@ -15,4 +15,25 @@
  */
 
 pub const PROMPT_COMMUNICATE: &'static [u8] = include_bytes!("./communicate.pr");
+pub const PROMPT_CONVERSATION: &'static [u8] = include_bytes!("./conversation.pr");
+pub const PROMPT_DESCRIBE: &'static [u8] = include_bytes!("./describe.pr");
 pub const PROMPT_SUMMARY: &'static [u8] = include_bytes!("./summary.pr");
+
+pub const PROMPT_COMMUNICATE_TEST: &'static [u8] = include_bytes!("./communicate.test.yml");
+pub const PROMPT_CONVERSATION_TEST: &'static [u8] = include_bytes!("./conversation.test.yml");
+pub const PROMPT_DESCRIBE_TEST: &'static [u8] = include_bytes!("./describe.test.yml");
+
+/// Every prompt that's installed, by file name
+pub const PROMPTS: &'static [(&'static str, &'static [u8])] = &[
+    ("communicate.pr", PROMPT_COMMUNICATE),
+    ("conversation.pr", PROMPT_CONVERSATION),
+    ("describe.pr", PROMPT_DESCRIBE),
+    ("summary.pr", PROMPT_SUMMARY),
+];
+
+/// The tests for the installed prompts that have them, by the file name of the prompt
+pub const PROMPT_TESTS: &'static [(&'static str, &'static [u8])] = &[
+    ("communicate.pr", PROMPT_COMMUNICATE_TEST),
+    ("conversation.pr", PROMPT_CONVERSATION_TEST),
+    ("describe.pr", PROMPT_DESCRIBE_TEST),
+];
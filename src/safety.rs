@@ -0,0 +1,312 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::path::{Component,Path};
+use crate::models::{ScriptFinding,ScriptRisk,ScriptSafety};
+use crate::shell::{ShellCommand,commands};
+
+/// Commands that wrap another command, the command they run is checked in their place
+const WRAPPERS: [&str; 8] = [
+    "command", "env", "exec", "nice", "nohup", "time", "timeout", "xargs"
+];
+
+/// Options of wrappers that take a value as the next word, so the value isn't taken for the command
+const WRAPPER_OPTIONS: [(&str, &[&str]); 6] = [
+    ("sudo", &[
+        "-u", "--user", "-g", "--group", "-h", "--host", "-p", "--prompt", "-C", "--close-from",
+        "-D", "--chdir", "-R", "--chroot", "-r", "--role", "-t", "--type",
+        "-T", "--command-timeout", "-U", "--other-user"
+    ]),
+    ("doas", &[ "-u", "-C" ]),
+    ("env", &[ "-u", "--unset", "-C", "--chdir" ]),
+    ("nice", &[ "-n", "--adjustment" ]),
+    ("timeout", &[ "-s", "--signal", "-k", "--kill-after" ]),
+    ("xargs", &[
+        "-a", "--arg-file", "-d", "--delimiter", "-E", "-I", "-L", "--max-lines",
+        "-n", "--max-args", "-P", "--max-procs", "-s", "--max-chars"
+    ]),
+];
+
+/// Actions of find that run a command on what it finds, up to a `;` or `+`
+const FIND_COMMANDS: [&str; 4] = [ "-exec", "-execdir", "-ok", "-okdir" ];
+
+/// Words that can come before a command without being one
+const KEYWORDS: [&str; 10] = [
+    "if", "then", "elif", "else", "while", "until", "do", "!", "{", "}"
+];
+
+const PRIVILEGED: [&str; 4] = [ "sudo", "su", "doas", "pkexec" ];
+
+const NETWORK: [&str; 14] = [
+    "curl", "wget", "ssh", "scp", "sftp", "ftp", "nc", "ncat", "telnet", "rsync", "aria2c",
+    "yt-dlp", "youtube-dl", "ping"
+];
+
+/// Git commands that talk to a remote
+const GIT_NETWORK: [&str; 4] = [ "clone", "fetch", "pull", "push" ];
+
+/// Paths outside of the current folder that are harmless to refer to
+const SAFE_PATHS: [&str; 4] = [ "/dev/null", "/dev/stdout", "/dev/stderr", "/dev/zero" ];
+
+/// Looks over a script for commands a reviewer should pay attention to before evaluating it
+pub fn analyze(script: &str, current_dir: &Path) -> ScriptSafety {
+    let mut findings = vec![];
+
+    for command in commands(script) {
+        analyze_command(&command, &command.words, current_dir, &mut findings);
+    }
+
+    ScriptSafety {
+        risk: findings.iter().map(|f| f.risk).max().unwrap_or(ScriptRisk::Low),
+        findings
+    }
+}
+
+fn analyze_command(
+    command: &ShellCommand,
+    words: &[String],
+    current_dir: &Path,
+    findings: &mut Vec<ScriptFinding>)
+{
+    // Keywords and variable assignments before the command, like `do LC_ALL=C sort`
+    let start = words.iter()
+        .position(|word| !is_assignment(word) && !KEYWORDS.contains(&word.as_str()));
+    let words = match start {
+        Some(start) => &words[start..],
+        None => return
+    };
+
+    let program = words[0].rsplit('/').next().unwrap_or_default();
+    let args = &words[1..];
+    let mut finding = |risk, reason: String| findings.push(ScriptFinding {
+        risk,
+        line: command.line,
+        command: command.words.join(" "),
+        reason
+    });
+
+    match program {
+        _ if PRIVILEGED.contains(&program) => {
+            finding(ScriptRisk::High, format!("Runs commands as another user with {program}"));
+        },
+        "rm" if is_recursive(args) && has_flag(args, 'f', "force") => {
+            finding(ScriptRisk::High,
+                String::from("Deletes folders and everything in them without asking"));
+        },
+        "rm" | "rmdir" | "shred" | "unlink" => {
+            finding(ScriptRisk::Medium, String::from("Deletes files"));
+        },
+        "find" if args.iter().any(|arg| arg == "-delete") => {
+            finding(ScriptRisk::Medium, String::from("Deletes the files find matches"));
+        },
+        "dd" => {
+            finding(ScriptRisk::High,
+                String::from("Writes raw data with dd, which can overwrite disks"));
+        },
+        _ if program.starts_with("mkfs") || [ "wipefs", "fdisk", "parted" ].contains(&program) => {
+            finding(ScriptRisk::High, format!("Formats or partitions disks with {program}"));
+        },
+        "chmod" | "chown" | "chgrp" if has_flag(args, 'R', "recursive") => {
+            finding(ScriptRisk::Medium, format!("Changes permissions recursively with {program}"));
+        },
+        "git" if args.first().is_some_and(|arg| GIT_NETWORK.contains(&arg.as_str())) => {
+            finding(ScriptRisk::Medium, String::from("Accesses the network with git"));
+        },
+        _ if NETWORK.contains(&program) => {
+            finding(ScriptRisk::Medium, format!("Accesses the network with {program}"));
+        },
+        _ => {}
+    }
+
+    let outside = args.iter()
+        .filter(|arg| is_outside(arg, current_dir))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if !outside.is_empty() {
+        finding(ScriptRisk::Medium, format!("Uses paths outside of the current folder: {}",
+            outside.join(", ")));
+    }
+
+    // The command these run is what matters
+    if WRAPPERS.contains(&program) || PRIVILEGED.contains(&program) {
+        analyze_command(command, &args[wrapped_start(program, args)..], current_dir, findings);
+    }
+
+    if program == "find" {
+        let mut rest = args;
+        while let Some(start) = rest.iter().position(|arg| FIND_COMMANDS.contains(&arg.as_str())) {
+            let run = &rest[start + 1..];
+            let end = run.iter().position(|arg| arg == ";" || arg == "+").unwrap_or(run.len());
+            analyze_command(command, &run[..end], current_dir, findings);
+            rest = &run[end..];
+        }
+    }
+}
+
+/// Where the command a wrapper runs starts among its arguments, after its options and their values
+fn wrapped_start(program: &str, args: &[String]) -> usize {
+    let options = WRAPPER_OPTIONS.iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, options)| *options)
+        .unwrap_or_default();
+    let mut start = 0;
+
+    while let Some(arg) = args.get(start) {
+        if arg == "--" {
+            start += 1;
+            break;
+        } else if !arg.starts_with('-') {
+            break;
+        }
+
+        start += if options.contains(&arg.as_str()) { 2 } else { 1 };
+    }
+
+    // The duration comes before the command
+    if program == "timeout" {
+        start += 1;
+    }
+
+    start.min(args.len())
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit()),
+        None => false
+    }
+}
+
+fn is_recursive(args: &[String]) -> bool {
+    has_flag(args, 'r', "recursive") || has_flag(args, 'R', "recursive")
+}
+
+/// Whether a short flag, which can be combined like `-rf`, or its long form is among `args`
+fn has_flag(args: &[String], short: char, long: &str) -> bool {
+    args.iter().any(|arg| match arg.strip_prefix("--") {
+        Some(name) => name == long,
+        None => arg.starts_with('-') && arg[1..].contains(short)
+    })
+}
+
+fn is_outside(arg: &str, current_dir: &Path) -> bool {
+    // Only the value of options like --output=/etc/foo
+    let arg = arg.split_once('=')
+        .filter(|_| arg.starts_with('-'))
+        .map(|(_, value)| value)
+        .unwrap_or(arg);
+
+    if SAFE_PATHS.contains(&arg) {
+        return false;
+    }
+
+    if arg == "~" || arg.starts_with("~/") || arg.starts_with("$HOME") {
+        return true;
+    }
+
+    let path = Path::new(arg);
+    if path.is_absolute() {
+        return !path.starts_with(current_dir);
+    }
+
+    // Relative paths that climb above the current folder
+    let mut depth = 0i32;
+    for component in path.components() {
+        match component {
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => depth += 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safety_findings() {
+        let dir = Path::new("/home/user/docs");
+        let safety = analyze("mkdir -p old\nmv *.md old/ 2> /dev/null", dir);
+        assert_eq!(safety.risk, ScriptRisk::Low);
+        assert!(safety.findings.is_empty());
+
+        let safety = analyze("for f in *.tmp; do rm \"$f\"; done", dir);
+        assert_eq!(safety.risk, ScriptRisk::Medium);
+        assert_eq!(safety.findings[0].line, 1);
+
+        let script = "cd build\nsudo rm -r -f /var/cache/foo\n\
+            curl -o x.zip https://example.com/x.zip";
+        let safety = analyze(script, dir);
+        assert_eq!(safety.risk, ScriptRisk::High);
+
+        let reasons = safety.findings.iter().map(|f| (f.line, f.risk)).collect::<Vec<_>>();
+        assert_eq!(reasons, vec![
+            (2, ScriptRisk::High),
+            (2, ScriptRisk::Medium),
+            (2, ScriptRisk::High),
+            (2, ScriptRisk::Medium),
+            (3, ScriptRisk::Medium),
+        ]);
+    }
+
+    #[test]
+    fn safety_wrapped_commands() {
+        let dir = Path::new("/home/user/docs");
+        let deletes = |script: &str| analyze(script, dir).findings.iter()
+            .any(|f| f.risk == ScriptRisk::High && f.reason.starts_with("Deletes folders"));
+
+        assert!(deletes("ls | xargs rm -rf"));
+        assert!(deletes("ls | xargs -I {} -n 1 rm -rf {}"));
+        assert!(deletes("rm -Rf build"));
+        assert!(deletes("sudo -u root rm -rf build"));
+        assert!(deletes("env -u HOME rm -rf build"));
+        assert!(deletes("nice -n 10 rm -rf build"));
+        assert!(deletes("timeout -s KILL 10 rm -rf build"));
+        assert!(deletes("find . -type d -exec rm -rf {} +"));
+        assert!(deletes("find . -name '*.o' -print -execdir rm -rf {} \\; -print"));
+        assert!(!deletes("find . -exec echo rm -rf {} \\;"));
+        assert!(!deletes("timeout 10 ls -rf"));
+
+        let safety = analyze("find . -name '*.tmp' -delete", dir);
+        assert_eq!(safety.risk, ScriptRisk::Medium);
+        assert_eq!(safety.findings[0].reason, "Deletes the files find matches");
+
+        let safety = analyze("find . -name '*.tmp' -exec rm {} +", dir);
+        assert_eq!(safety.risk, ScriptRisk::Medium);
+        assert_eq!(safety.findings[0].reason, "Deletes files");
+    }
+
+    #[test]
+    fn safety_outside_paths() {
+        let dir = Path::new("/home/user/docs");
+
+        assert!(is_outside("../secrets", dir));
+        assert!(is_outside("~/.ssh", dir));
+        assert!(is_outside("--output=/etc/hosts", dir));
+        assert!(!is_outside("a/../b", dir));
+        assert!(!is_outside("/home/user/docs/a", dir));
+        assert!(!is_outside("/dev/null", dir));
+    }
+}
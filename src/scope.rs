@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+use std::path::{Path,PathBuf};
+use crate::models::{ActionScope,FolderKind};
+use crate::targets::glob_matches;
+
+/// Whether an action saved in the folder at `origin` is suggested in the folder at `path`, which
+/// is of the given `kinds`
+pub fn scope_matches(scope: &ActionScope, origin: &Path, path: &Path, kinds: &[FolderKind]) -> bool {
+    if origin == path {
+        return true;
+    }
+
+    match scope {
+        ActionScope::Folder => false,
+        ActionScope::Tree => path.starts_with(origin),
+        ActionScope::Pattern { glob } => glob_matches(
+            &expand_home(glob).to_string_lossy(),
+            &path.to_string_lossy()),
+        ActionScope::Kind { kind } => kinds.contains(kind)
+    }
+}
+
+/// The kinds of the folder at `path`, going by the names of the entries directly in it
+pub fn folder_kinds(path: &Path) -> Vec<FolderKind> {
+    match fs::read_dir(path) {
+        Ok(entries) => kinds_of(entries
+            .flatten()
+            .map(|entry| (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.file_type().map(|t| t.is_file()).unwrap_or(false)
+            ))),
+        Err(_) => vec![]
+    }
+}
+
+/// Works out the kinds of a folder from the names of its entries and whether they're files. A
+/// folder is "mostly" some kind of media when more than half of its files are.
+fn kinds_of(entries: impl Iterator<Item = (String, bool)>) -> Vec<FolderKind> {
+    let mut kinds = vec![];
+    let mut files = 0;
+    let mut media = [
+        (FolderKind::Images, 0),
+        (FolderKind::Music, 0),
+        (FolderKind::Videos, 0),
+        (FolderKind::Documents, 0)
+    ];
+
+    for (name, is_file) in entries {
+        let marker = match name.as_str() {
+            "Cargo.toml" => Some(FolderKind::Rust),
+            "package.json" => Some(FolderKind::Node),
+            ".git" => Some(FolderKind::Git),
+            _ => None
+        };
+
+        if let Some(kind) = marker {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+
+        if is_file {
+            files += 1;
+
+            if let Some(kind) = media_kind(&name) {
+                for (candidate, count) in &mut media {
+                    if *candidate == kind {
+                        *count += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    kinds.extend(media.into_iter()
+        .filter(|(_, count)| *count * 2 > files)
+        .map(|(kind, _)| kind));
+    kinds
+}
+
+fn media_kind(name: &str) -> Option<FolderKind> {
+    let extension = Path::new(name).extension()?.to_string_lossy().to_lowercase();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp" | "tif" | "tiff" | "heic" | "svg" | "raw" => {
+            Some(FolderKind::Images)
+        },
+        "mp3" | "flac" | "ogg" | "opus" | "wav" | "m4a" | "aac" => Some(FolderKind::Music),
+        "mp4" | "mkv" | "webm" | "mov" | "avi" | "m4v" => Some(FolderKind::Videos),
+        "pdf" | "doc" | "docx" | "odt" | "txt" | "md" | "xls" | "xlsx" | "ods" | "ppt" | "pptx" | "odp" => {
+            Some(FolderKind::Documents)
+        },
+        _ => None
+    }
+}
+
+fn expand_home(glob: &str) -> PathBuf {
+    match (glob.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(glob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_matches_folders() {
+        let origin = Path::new("/home/user/code");
+        let project = Path::new("/home/user/code/aerome");
+        let elsewhere = Path::new("/home/user/pictures");
+
+        assert!(scope_matches(&ActionScope::Folder, origin, origin, &[]));
+        assert!(!scope_matches(&ActionScope::Folder, origin, project, &[]));
+        assert!(scope_matches(&ActionScope::Tree, origin, project, &[]));
+        assert!(!scope_matches(&ActionScope::Tree, origin, elsewhere, &[]));
+
+        let pattern = ActionScope::Pattern { glob: "/home/*/code/*".into() };
+        assert!(scope_matches(&pattern, elsewhere, project, &[]));
+        assert!(!scope_matches(&pattern, project, elsewhere, &[]));
+
+        let kind = ActionScope::Kind { kind: FolderKind::Rust };
+        assert!(scope_matches(&kind, elsewhere, project, &[ FolderKind::Git, FolderKind::Rust ]));
+        assert!(!scope_matches(&kind, elsewhere, project, &[ FolderKind::Images ]));
+    }
+
+    #[test]
+    fn scope_folder_kinds() {
+        let entries = |names: &[(&str, bool)]| names.iter()
+            .map(|(name, is_file)| (name.to_string(), *is_file))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        assert_eq!(kinds_of(entries(&[
+            ("Cargo.toml", true), (".git", false), ("src", false), ("README.md", true)
+        ])), vec![ FolderKind::Rust, FolderKind::Git ]);
+
+        assert_eq!(kinds_of(entries(&[
+            ("a.JPG", true), ("b.png", true), ("notes.txt", true), ("raw", false)
+        ])), vec![ FolderKind::Images ]);
+
+        assert_eq!(kinds_of(entries(&[ ("a.jpg", true), ("b.pdf", true) ])), vec![]);
+    }
+}
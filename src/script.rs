@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::env;
+use std::path::{Path,PathBuf};
+use std::process::Stdio;
+use std::os::unix::process::CommandExt;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicU32,Ordering};
+use std::time::Duration;
+use derive_more::Display;
+use tokio::io::{AsyncRead,AsyncReadExt};
+use tokio::process::Command;
+use tokio::runtime::Runtime;
+use tokio::task::AbortHandle;
+use crate::models::{ScriptOutputLine,ScriptOutputStream,ScriptPolicy};
+
+#[derive(Debug, Display)]
+pub enum ScriptError {
+    #[display(fmt = "{}", _0)]
+    Failed(String),
+    #[display(fmt = "The script was stopped after running for {} seconds", _0)]
+    Timeout(u64),
+    #[display(fmt = "The script was stopped after printing more than {} bytes", _0)]
+    OutputTooLarge(usize),
+    #[display(fmt = "The script couldn't be run: {}", _0)]
+    IO(std::io::Error),
+    #[display(fmt = "The script wasn't run, {} needs bubblewrap (bwrap) which isn't installed", _0)]
+    NoSandbox(&'static str)
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Runs one script at a time on the app's runtime, so the window stays responsive and the script
+/// can be cancelled
+#[derive(Clone)]
+pub struct ScriptRunner {
+    running: Arc<Mutex<Option<RunningScript>>>
+}
+
+struct RunningScript {
+    /// The script is the leader of its own process group, so this is its pid as well. It's zero
+    /// until the process has been spawned.
+    pgid: Arc<AtomicU32>,
+    abort: AbortHandle
+}
+
+impl ScriptRunner {
+    pub fn new() -> Self {
+        Self {
+            running: Arc::new(Mutex::new(None))
+        }
+    }
+
+    /// Starts the script, cancelling whatever was running before. Output is handed to `on_output`
+    /// a line at a time, and `on_finish` gets the result unless the script is cancelled.
+    pub fn start(
+        &self,
+        rt: &Runtime,
+        bash_script: String,
+        current_dir: PathBuf,
+        policy: ScriptPolicy,
+        on_output: impl FnMut(ScriptOutputLine) + Send + 'static,
+        on_finish: impl FnOnce(Result<String, ScriptError>) + Send + 'static)
+    {
+        self.cancel();
+
+        let pgid = Arc::new(AtomicU32::new(0));
+        let task_pgid = pgid.clone();
+        let running = self.running.clone();
+
+        // Held until the script is registered so it can't finish and unregister itself first
+        let mut guard = self.running.lock().unwrap();
+        let handle = rt.spawn(async move {
+            let on_spawn = |pid| task_pgid.store(pid, Ordering::SeqCst);
+            let result = run_script(bash_script, &current_dir, &policy, on_spawn, on_output).await;
+
+            let mut running = running.lock().unwrap();
+            if running.as_ref().map(|r| Arc::ptr_eq(&r.pgid, &task_pgid)).unwrap_or(false) {
+                running.take();
+            }
+            drop(running);
+
+            on_finish(result);
+        });
+
+        *guard = Some(RunningScript { pgid, abort: handle.abort_handle() });
+    }
+
+    /// Kills the running script along with anything it started, returning false if nothing was
+    /// running
+    pub fn cancel(&self) -> bool {
+        match self.running.lock().unwrap().take() {
+            Some(script) => {
+                script.abort.abort();
+
+                match script.pgid.load(Ordering::SeqCst) {
+                    0 => {},
+                    pgid => kill_process_group(pgid)
+                }
+                true
+            },
+            None => false
+        }
+    }
+}
+
+/// Runs a script for a preview with `copy` mounted over `current_dir`, the rest of the filesystem
+/// read only and no network, so nothing it does reaches the real files. Without bubblewrap that
+/// can't be guaranteed, so the script isn't run at all.
+pub fn run_preview_sync(
+    bash_script: String,
+    current_dir: &Path,
+    copy: &Path,
+    policy: &ScriptPolicy) -> Result<String, ScriptError>
+{
+    let bwrap = find_executable("bwrap").ok_or(ScriptError::NoSandbox("previewing it"))?;
+    let mut cmd = std::process::Command::new(bwrap);
+
+    cmd.args(&[ "--ro-bind", "/", "/", "--tmpfs", "/tmp" ])
+        .arg("--bind").arg(copy).arg(current_dir)
+        .args(&[ "--unshare-net", "--dev", "/dev", "--proc", "/proc", "--die-with-parent" ])
+        .arg("--chdir").arg(current_dir)
+        .args(&[ "/bin/bash", "-c", bash_script.as_str() ]);
+
+    Runtime::new()
+        .unwrap()
+        .block_on(async move { run_command(spawnable(cmd, current_dir), policy, |_| {}, |_| {}).await })
+}
+
+/// Runs a script in `current_dir` within the limits of `policy`. Restricting writes and network
+/// access relies on bubblewrap, without it the script runs with the user's full privileges and
+/// `sandbox_missing` says so.
+pub async fn run_script(
+    bash_script: String,
+    current_dir: &Path,
+    policy: &ScriptPolicy,
+    on_spawn: impl FnOnce(u32),
+    on_output: impl FnMut(ScriptOutputLine)) -> Result<String, ScriptError>
+{
+    run_command(command(&bash_script, current_dir, policy), policy, on_spawn, on_output).await
+}
+
+async fn run_command(
+    mut cmd: Command,
+    policy: &ScriptPolicy,
+    on_spawn: impl FnOnce(u32),
+    mut on_output: impl FnMut(ScriptOutputLine)) -> Result<String, ScriptError>
+{
+    let mut child = cmd
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(ScriptError::IO)?;
+
+    let pid = child.id();
+    if let Some(pid) = pid {
+        on_spawn(pid);
+    }
+
+    let max = policy.max_output_bytes;
+    let mut stdout = OutputLines::new(child.stdout.take().unwrap());
+    let mut stderr = OutputLines::new(child.stderr.take().unwrap());
+
+    let finished = async {
+        let mut output = String::new();
+        let mut errors = String::new();
+
+        while !stdout.done || !stderr.done {
+            let (stream, lines) = tokio::select! {
+                lines = stdout.next(max), if !stdout.done => (ScriptOutputStream::Stdout, lines),
+                lines = stderr.next(max), if !stderr.done => (ScriptOutputStream::Stderr, lines),
+            };
+
+            let buffer = match stream {
+                ScriptOutputStream::Stdout => &mut output,
+                ScriptOutputStream::Stderr => &mut errors
+            };
+
+            for text in lines? {
+                buffer.push_str(&text);
+                buffer.push('\n');
+                on_output(ScriptOutputLine { stream, text });
+            }
+        }
+
+        let status = child.wait().await.map_err(ScriptError::IO)?;
+        Ok((status, output, errors))
+    };
+
+    let result = tokio::time::timeout(Duration::from_secs(policy.timeout_secs), finished)
+        .await
+        .map_err(|_| ScriptError::Timeout(policy.timeout_secs))
+        .and_then(|result| result);
+
+    match result {
+        Ok((status, output, _)) if status.success() => Ok(output),
+        Ok((_, _, errors)) => Err(ScriptError::Failed(errors)),
+        Err(e) => {
+            // Anything the script started in the background goes down with it
+            if let Some(pid) = pid {
+                kill_process_group(pid);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Reads a stream in fixed size chunks and splits lines out of them, so a script printing without
+/// newlines can't make it buffer more than the output limit
+struct OutputLines<R> {
+    reader: R,
+    pending: Vec<u8>,
+    read: usize,
+    done: bool
+}
+
+impl<R: AsyncRead + Unpin> OutputLines<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, pending: vec![], read: 0, done: false }
+    }
+
+    /// The lines completed by the next chunk, or whatever is left once the stream has ended. Fails
+    /// as soon as more than `max` bytes have been read in all.
+    async fn next(&mut self, max: usize) -> Result<Vec<String>, ScriptError> {
+        let mut chunk = [0; OUTPUT_CHUNK_BYTES];
+        let limit = chunk.len().min(max.saturating_sub(self.read) + 1);
+        let count = self.reader.read(&mut chunk[..limit]).await.map_err(ScriptError::IO)?;
+
+        self.read += count;
+        if self.read > max {
+            return Err(ScriptError::OutputTooLarge(max));
+        }
+
+        if count == 0 {
+            self.done = true;
+            let rest = std::mem::take(&mut self.pending);
+            return Ok(if rest.is_empty() { vec![] } else { vec![String::from_utf8_lossy(&rest).into_owned()] });
+        }
+
+        self.pending.extend_from_slice(&chunk[..count]);
+
+        let mut lines = vec![];
+        while let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line = self.pending.drain(..=end).collect::<Vec<_>>();
+            lines.push(String::from_utf8_lossy(&line[..end]).into_owned());
+        }
+
+        Ok(lines)
+    }
+}
+
+/// Whether `policy` asks for restrictions that can't be enforced because bubblewrap isn't installed
+pub fn sandbox_missing(policy: &ScriptPolicy) -> bool {
+    (policy.restrict_writes || !policy.allow_network) && find_executable("bwrap").is_none()
+}
+
+fn command(bash_script: &str, current_dir: &Path, policy: &ScriptPolicy) -> Command {
+    let sandboxed = policy.restrict_writes || !policy.allow_network;
+
+    let cmd = match find_executable("bwrap") {
+        Some(bwrap) if sandboxed => {
+            let mut cmd = std::process::Command::new(bwrap);
+
+            if policy.restrict_writes {
+                cmd.args(&[ "--ro-bind", "/", "/", "--tmpfs", "/tmp" ])
+                    .arg("--bind").arg(current_dir).arg(current_dir);
+            } else {
+                cmd.args(&[ "--bind", "/", "/" ]);
+            }
+
+            if !policy.allow_network {
+                cmd.arg("--unshare-net");
+            }
+
+            cmd.args(&[ "--dev", "/dev", "--proc", "/proc", "--die-with-parent" ])
+                .arg("--chdir").arg(current_dir)
+                .args(&[ "/bin/bash", "-c", bash_script ]);
+            cmd
+        },
+        _ => {
+            if sandboxed {
+                log::warn!("bwrap wasn't found, running the script without a sandbox");
+            }
+
+            let mut cmd = std::process::Command::new("/bin/bash");
+            cmd.args(&[ "-c", bash_script ]);
+            cmd
+        }
+    };
+
+    spawnable(cmd, current_dir)
+}
+
+fn spawnable(mut cmd: std::process::Command, current_dir: &Path) -> Command {
+    // A process group of its own lets cancelling take down everything the script spawned
+    cmd.current_dir(current_dir)
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    Command::from(cmd)
+}
+
+fn kill_process_group(pgid: u32) {
+    // SAFETY: kill has no memory safety requirements, a stale pgid at worst fails with ESRCH
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+fn find_executable(name: &str) -> Option<PathBuf> {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).map(|dir| dir.join(name)).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+const OUTPUT_CHUNK_BYTES: usize = 8 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_output_lines() {
+        let rt = Runtime::new().unwrap();
+
+        let mut lines = OutputLines::new(&b"one\ntwo\nthree"[..]);
+        assert_eq!(rt.block_on(lines.next(100)).unwrap(), vec![ "one", "two" ]);
+        assert_eq!(rt.block_on(lines.next(100)).unwrap(), vec![ "three" ]);
+        assert!(lines.done);
+
+        // Output without newlines stops at the limit rather than being buffered until it ends
+        let endless = vec![b'y'; 4 * OUTPUT_CHUNK_BYTES];
+        let mut lines = OutputLines::new(&endless[..]);
+        let result = rt.block_on(async {
+            loop {
+                match lines.next(OUTPUT_CHUNK_BYTES + 10).await {
+                    Ok(read) => assert!(read.is_empty()),
+                    Err(e) => return e
+                }
+            }
+        });
+        assert!(matches!(result, ScriptError::OutputTooLarge(_)));
+        assert!(lines.pending.len() <= OUTPUT_CHUNK_BYTES + 10);
+    }
+}
@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+/// A simple command in a script, like `rm -rf foo` in `cd bar && rm -rf foo`
+#[derive(Debug, PartialEq)]
+pub struct ShellCommand {
+    /// The line the command starts on, counting from one
+    pub line: usize,
+    pub words: Vec<String>
+}
+
+/// Splits a script into simple commands made of words, with quotes removed. This doesn't
+/// understand all of bash, command substitutions are left within the words they appear in and
+/// keywords like `for` and `do` become commands of their own.
+pub fn commands(script: &str) -> Vec<ShellCommand> {
+    let mut commands = vec![];
+    let mut command = ShellCommand { line: 1, words: vec![] };
+    let mut word = String::new();
+    let mut quote = None;
+    let mut line = 1;
+    let mut chars = script.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (Some('\''), _) => word.push(c),
+            (_, '\\') => match chars.next() {
+                Some('\n') => line += 1,
+                Some(c) => word.push(c),
+                None => {}
+            },
+            (None, '#') if word.is_empty() => {
+                if chars.by_ref().any(|c| c == '\n') {
+                    line += 1;
+                }
+                end_command(&mut commands, &mut command, &mut word, line);
+            },
+            (None, c) if "\n;|&()`".contains(c) => end_command(&mut commands, &mut command, &mut word, line),
+            (None, c) if c.is_whitespace() || "<>".contains(c) => end_word(&mut command, &mut word),
+            _ => word.push(c)
+        }
+    }
+
+    end_command(&mut commands, &mut command, &mut word, line);
+    commands
+}
+
+fn end_word(command: &mut ShellCommand, word: &mut String) {
+    if !word.is_empty() {
+        command.words.push(std::mem::take(word));
+    }
+}
+
+fn end_command(commands: &mut Vec<ShellCommand>, command: &mut ShellCommand, word: &mut String, line: usize) {
+    end_word(command, word);
+
+    let next = ShellCommand { line, words: vec![] };
+    let command = std::mem::replace(command, next);
+
+    if !command.words.is_empty() {
+        commands.push(command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_commands() {
+        let script = "# Tidy up\ncd \"my photos\" && rm -rf 'old stuff'\nmv a\\ b.jpg c.jpg; echo 'it''s'|wc -l";
+
+        assert_eq!(commands(script), vec![
+            ShellCommand { line: 2, words: vec![ "cd".into(), "my photos".into() ] },
+            ShellCommand { line: 2, words: vec![ "rm".into(), "-rf".into(), "old stuff".into() ] },
+            ShellCommand { line: 3, words: vec![ "mv".into(), "a b.jpg".into(), "c.jpg".into() ] },
+            ShellCommand { line: 3, words: vec![ "echo".into(), "its".into() ] },
+            ShellCommand { line: 3, words: vec![ "wc".into(), "-l".into() ] },
+        ]);
+    }
+}
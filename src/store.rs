@@ -17,8 +17,12 @@
 use dirs;
 use cozo::{self,Db,SqliteStorage,DataValue};
 use crate::constants::APP_NAME;
-use crate::models::{Action,Account,AccountDirect,AccountAerome,Settings,Suggestions};
-use std::{fs,path::{PathBuf,Path},collections::BTreeMap};
+use crate::models::{Action,ActionConflict,ActionImport,ActionScope,BundledAction,Account,AccountDirect,AccountAerome,AccountLocal,ConversationItem,Operation,ScriptPolicy,Settings,Suggestions,TrashRetention,Usage,UsageReport};
+use crate::safety::analyze;
+use crate::scope::{folder_kinds,scope_matches};
+use crate::template;
+use std::{fs,path::{PathBuf,Path},collections::{BTreeMap,HashMap}};
+use chrono::{Local,Utc};
 
 #[derive(Clone)]
 pub struct Store {
@@ -46,11 +50,64 @@ impl Store {
                 inserted: Validity default 'ASSERT'
             }
         "#, Default::default());
+        let _ = db.run_script(r#"
+            :create journal {
+                id: Int =>
+                operation: String,
+                undone: Bool
+            }
+        "#, Default::default());
+        let _ = db.run_script(r#"
+            :create action_stats {
+                path: String,
+                code: String =>
+                pinned: Bool,
+                uses: Int,
+                last_used: Int
+            }
+        "#, Default::default());
+        let _ = db.run_script(r#"
+            :create action_scopes {
+                path: String,
+                code: String =>
+                scope: String
+            }
+        "#, Default::default());
+        let _ = db.run_script(r#"
+            :create descriptions {
+                path: String =>
+                listing: String,
+                purpose: String,
+                actions: String
+            }
+        "#, Default::default());
+        let _ = db.run_script(r#"
+            :create usage {
+                at: Int,
+                prompt: String =>
+                day: String,
+                account: String,
+                model: String,
+                prompt_tokens: Int,
+                completion_tokens: Int,
+                estimated: Bool
+            }
+        "#, Default::default());
+        let _ = db.run_script(r#"
+            :create conversations {
+                path: String,
+                id: Int =>
+                item: String
+            }
+        "#, Default::default());
 
         Store { db }
     }
 
+    /// Saves an action for the folder at `path`, replacing any action with the same code
     pub fn add_suggestion(&self, path: &Path, action: &Action) {
+        self.remove_actions(path, &action.code);
+
         let params: BTreeMap<String, DataValue> = vec![
             (String::from("code"), DataValue::Str(action.code.clone().into())),
             (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
@@ -75,6 +132,105 @@ impl Store {
         ", params).unwrap();
     }
 
+    /// The folder an action suggested in `path` was saved in, which is where its stats are kept
+    pub fn suggestion_origin(&self, path: &Path, code: &str) -> PathBuf {
+        self.get_suggestions(path).actions.into_iter()
+            .find(|action| action.code == code)
+            .and_then(|action| action.origin)
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// Counts a use of the action with `code`, whether or not it's been saved yet
+    pub fn use_suggestion(&self, path: &Path, code: &str) {
+        let stats = self.get_action_stats(path);
+        let (pinned, uses, _) = stats.get(code).copied().unwrap_or((false, 0, 0));
+
+        self.set_action_stats(path, code, pinned, uses + 1, Utc::now().timestamp());
+    }
+
+    pub fn pin_suggestion(&self, path: &Path, code: &str, pinned: bool) {
+        let stats = self.get_action_stats(path);
+        let (_, uses, last_used) = stats.get(code).copied().unwrap_or((false, 0, 0));
+
+        self.set_action_stats(path, code, pinned, uses, last_used);
+    }
+
+    pub fn rename_suggestion(&self, path: &Path, code: &str, description: &str) {
+        if let Some(mut action) = self.get_action(path, code) {
+            action.description = Some(description.to_string());
+            self.add_suggestion(path, &action);
+        }
+    }
+
+    /// Changes the code of an action, taking the place of any action that already has that code
+    pub fn edit_suggestion(&self, path: &Path, code: &str, new_code: &str) {
+        if code == new_code {
+            return;
+        }
+
+        if let Some(mut action) = self.get_action(path, code) {
+            let stats = self.get_action_stats(path);
+
+            self.delete_suggestion(path, code);
+            action.code = new_code.to_string();
+            self.add_suggestion(path, &action);
+            self.scope_suggestion(path, new_code, &action.scope);
+
+            if let Some((pinned, uses, last_used)) = stats.get(code).copied() {
+                self.set_action_stats(path, new_code, pinned, uses, last_used);
+            }
+        }
+    }
+
+    /// Removes an action, including one suggested by the folder's description
+    pub fn delete_suggestion(&self, path: &Path, code: &str) {
+        self.remove_actions(path, code);
+
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+            (String::from("code"), DataValue::Str(code.into()))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ path, code ] := *action_stats { path, code }, path == $path, code == $code
+            :rm action_stats { path, code }
+        ", params.clone()).unwrap();
+
+        self.db.run_script("
+            ?[ path, code ] := *action_scopes { path, code }, path == $path, code == $code
+            :rm action_scopes { path, code }
+        ", params).unwrap();
+
+        if let Some((listing, mut description)) = self.get_description(path) {
+            description.actions.retain(|action| action.code != code);
+            self.set_description(path, &listing, &description);
+        }
+    }
+
+    /// Sets where else the action saved for the folder at `path` is suggested
+    pub fn scope_suggestion(&self, path: &Path, code: &str, scope: &ActionScope) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+            (String::from("code"), DataValue::Str(code.into())),
+            (String::from("scope"), DataValue::Str(serde_json::to_string(scope).unwrap().into()))
+        ].into_iter().collect();
+
+        if *scope == ActionScope::Folder {
+            self.db.run_script("
+                ?[ path, code ] := *action_scopes { path, code }, path == $path, code == $code
+                :rm action_scopes { path, code }
+            ", params).unwrap();
+        } else {
+            self.db.run_script("
+                ?[ path, code, scope ] <- [[ $path, $code, $scope ]]
+                :put action_scopes { path, code => scope }
+            ", params).unwrap();
+        }
+    }
+
+    /// The actions saved for the folder at `path`, along with those saved elsewhere whose scope
+    /// takes in the folder, pinned actions first and then by how often and how recently they were
+    /// used
     pub fn get_suggestions(&self, path: &Path) -> Suggestions {
         let params: BTreeMap<String, DataValue> = vec![
             (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
@@ -85,29 +241,508 @@ impl Store {
                 *actions { code, description, path, question, inserted },
                 path == $path
 
-            :sort inserted
+            :sort -inserted
         ", params).unwrap();
 
-        let actions = result.rows.into_iter()
+        let scoped = self.get_scoped_actions();
+        let kinds = folder_kinds(path);
+        let now = Utc::now().timestamp();
+
+        let mut actions = result.rows.into_iter()
             .filter_map(|row| {
                 use DataValue::*;
                 match &row[..] {
                     [ Str(code), Str(description), Str(path), Str(question), _ ] => Some(Action {
                         code: (&**code).to_owned(),
                         question: (&**question).to_owned(),
-                        description: Some((&**description).to_owned())
+                        description: Some((&**description).to_owned()),
+                        ..Default::default()
                     }),
                     _ => None
                 }
             })
-            .collect();
+            .map(|mut action| {
+                action.scope = scoped.iter()
+                    .find(|(origin, scoped)| origin == path && scoped.code == action.code)
+                    .map(|(_, scoped)| scoped.scope.clone())
+                    .unwrap_or_default();
+                action
+            })
+            .chain(scoped.iter()
+                .filter(|(origin, action)| origin != path
+                    && scope_matches(&action.scope, origin, path, &kinds))
+                .map(|(origin, action)| Action {
+                    origin: Some(origin.clone()),
+                    ..action.clone()
+                }))
+            .fold(Vec::<Action>::new(), |mut actions, action| {
+                // Rows from before actions were unique by code, the newest one wins. Actions saved
+                // for the folder itself come before those from elsewhere with the same code.
+                if !actions.iter().any(|a| a.code == action.code) {
+                    actions.push(action);
+                }
+                actions
+            });
+
+        let mut origin_stats = HashMap::new();
+        let stats = actions.iter()
+            .filter_map(|action| {
+                let origin = action.origin.as_deref().unwrap_or(path);
+                let stats = origin_stats
+                    .entry(origin.to_path_buf())
+                    .or_insert_with(|| self.get_action_stats(origin));
+
+                Some((action.code.clone(), *stats.get(&action.code)?))
+            })
+            .collect::<HashMap<_, _>>();
+
+        for action in &mut actions {
+            if let Some((pinned, uses, _)) = stats.get(&action.code) {
+                action.pinned = *pinned;
+                action.uses = *uses as u32;
+            }
+        }
+
+        let score = |action: &Action| stats.get(&action.code)
+            .map(|(_, uses, last_used)| frecency(*uses, *last_used, now))
+            .unwrap_or(0.);
+
+        actions.sort_by(|a, b| b.pinned.cmp(&a.pinned)
+            .then_with(|| score(b).partial_cmp(&score(a)).unwrap_or(std::cmp::Ordering::Equal)));
+
+        let (purpose, candidates) = self.get_description(path)
+            .map(|(_, description)| (description.purpose, description.actions))
+            .unwrap_or_default();
+
+        // The actions from the folder's description fill in after the ones that were used
+        for candidate in candidates {
+            if !actions.iter().any(|action| action.question == candidate.question || action.code == candidate.code) {
+                actions.push(candidate);
+            }
+        }
+
+        // Actions saved elsewhere or suggested by a description haven't been reviewed for this
+        // folder, so every one of them is analyzed again
+        for action in &mut actions {
+            action.parameters = template::parameters(&action.code);
+            action.safety = Some(analyze(&action.code, path));
+        }
 
         Suggestions {
-            purpose: String::new(),
+            purpose,
             actions
         }
     }
 
+    /// Every action that has a scope, with the folder it was saved in, newest first
+    fn get_scoped_actions(&self) -> Vec<(PathBuf, Action)> {
+        let result = self.db.run_script("
+            ?[ code, description, path, question, inserted, scope ] :=
+                *actions { code, description, path, question, inserted },
+                *action_scopes { path, code, scope }
+
+            :sort -inserted
+        ", BTreeMap::new()).unwrap();
+
+        result.rows.into_iter()
+            .filter_map(|row| {
+                use DataValue::*;
+                match &row[..] {
+                    [ Str(code), Str(description), Str(path), Str(question), _, Str(scope) ] => Some((
+                        PathBuf::from(&**path),
+                        Action {
+                            code: (&**code).to_owned(),
+                            question: (&**question).to_owned(),
+                            description: Some((&**description).to_owned()),
+                            scope: serde_json::from_str(scope).ok()?,
+                            ..Default::default()
+                        }
+                    )),
+                    _ => None
+                }
+            })
+            .collect()
+    }
+
+    fn get_action(&self, path: &Path, code: &str) -> Option<Action> {
+        self.get_suggestions(path).actions.into_iter()
+            .find(|action| action.code == code && action.origin.is_none())
+    }
+
+    /// Removes every saved row for the action with `code`, all of its past versions included
+    fn remove_actions(&self, path: &Path, code: &str) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+            (String::from("code"), DataValue::Str(code.into()))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ code, path, question, description, inserted ] :=
+                *actions { code, path, question, description, inserted },
+                path == $path,
+                code == $code
+            :rm actions { code, path, question, description, inserted }
+        ", params).unwrap();
+    }
+
+    /// Whether each action in the folder at `path` is pinned, how often it's been used and when
+    /// it was last used, by code
+    fn get_action_stats(&self, path: &Path) -> HashMap<String, (bool, i64, i64)> {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ code, pinned, uses, last_used ] :=
+                *action_stats { path, code, pinned, uses, last_used },
+                path == $path
+        ", params).unwrap();
+
+        result.rows.into_iter()
+            .filter_map(|row| match &row[..] {
+                [ DataValue::Str(code), DataValue::Bool(pinned), uses, last_used ] => Some((
+                    code.to_string(),
+                    (*pinned, uses.get_int()?, last_used.get_int()?)
+                )),
+                _ => None
+            })
+            .collect()
+    }
+
+    fn set_action_stats(&self, path: &Path, code: &str, pinned: bool, uses: i64, last_used: i64) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+            (String::from("code"), DataValue::Str(code.into())),
+            (String::from("pinned"), pinned.into()),
+            (String::from("uses"), DataValue::from(uses)),
+            (String::from("last_used"), DataValue::from(last_used))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ path, code, pinned, uses, last_used ] <- [[ $path, $code, $pinned, $uses, $last_used ]]
+            :put action_stats { path, code => pinned, uses, last_used }
+        ", params).unwrap();
+    }
+
+    /// Every saved action, or only those saved in folders under `prefix`
+    pub fn export_actions(&self, prefix: Option<&Path>) -> Vec<BundledAction> {
+        let result = self.db.run_script("
+            ?[ code, description, path, question, inserted ] :=
+                *actions { code, description, path, question, inserted }
+
+            :sort path, code, -inserted
+        ", BTreeMap::new()).unwrap();
+
+        let scoped = self.get_scoped_actions();
+
+        result.rows.into_iter()
+            .filter_map(|row| {
+                use DataValue::*;
+                match &row[..] {
+                    [ Str(code), Str(description), Str(path), Str(question), _ ] => Some(BundledAction {
+                        path: PathBuf::from(&**path),
+                        code: (&**code).to_owned(),
+                        question: (&**question).to_owned(),
+                        description: (&**description).to_owned(),
+                        ..Default::default()
+                    }),
+                    _ => None
+                }
+            })
+            .filter(|action| prefix.map(|prefix| action.path.starts_with(prefix)).unwrap_or(true))
+            .fold(Vec::<BundledAction>::new(), |mut actions, mut action| {
+                if !actions.iter().any(|a| a.path == action.path && a.code == action.code) {
+                    action.scope = scoped.iter()
+                        .find(|(origin, scoped)| *origin == action.path && scoped.code == action.code)
+                        .map(|(_, scoped)| scoped.scope.clone())
+                        .unwrap_or_default();
+                    actions.push(action);
+                }
+                actions
+            })
+    }
+
+    /// Saves the `actions` that aren't saved yet. Those that conflict with an action already
+    /// saved are only saved if `replace` is set.
+    pub fn import_actions(&self, actions: &[BundledAction], replace: bool) -> ActionImport {
+        let existing = self.export_actions(None);
+        let mut import = ActionImport::default();
+
+        for action in actions {
+            match existing.iter().find(|e| e.path == action.path && e.code == action.code) {
+                Some(ours) if ours == action => {
+                    import.unchanged += 1;
+                    continue;
+                },
+                Some(ours) if !replace => {
+                    import.conflicts.push(ActionConflict {
+                        ours: ours.clone(),
+                        theirs: action.clone()
+                    });
+                    continue;
+                },
+                Some(_) => import.replaced += 1,
+                None => import.added += 1
+            }
+
+            self.add_suggestion(&action.path, &Action {
+                code: action.code.clone(),
+                question: action.question.clone(),
+                description: Some(action.description.clone()),
+                ..Default::default()
+            });
+            self.scope_suggestion(&action.path, &action.code, &action.scope);
+        }
+
+        import
+    }
+
+    /// Records the tokens used by running `prompt` for `account`, on the current day in local time.
+    /// `estimated` is set when the counts weren't reported by the API.
+    pub fn record_usage(
+        &self,
+        prompt: &str,
+        account: &str,
+        model: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        estimated: bool)
+    {
+        let now = Utc::now();
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("at"), DataValue::from(now.timestamp_micros())),
+            (String::from("prompt"), DataValue::Str(prompt.into())),
+            (String::from("day"), DataValue::Str(now.with_timezone(&Local).format("%Y-%m-%d").to_string().into())),
+            (String::from("account"), DataValue::Str(account.into())),
+            (String::from("model"), DataValue::Str(model.into())),
+            (String::from("prompt_tokens"), DataValue::from(prompt_tokens as i64)),
+            (String::from("completion_tokens"), DataValue::from(completion_tokens as i64)),
+            (String::from("estimated"), DataValue::Bool(estimated))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ at, prompt, day, account, model, prompt_tokens, completion_tokens, estimated ] <- [[
+                $at, $prompt, $day, $account, $model, $prompt_tokens, $completion_tokens, $estimated
+            ]]
+            :put usage { at, prompt => day, account, model, prompt_tokens, completion_tokens, estimated }
+        ", params).unwrap();
+    }
+
+    /// The tokens used on each day since `since`, a day like "2023-06-01", or on every day
+    pub fn get_usage(&self, since: Option<&str>) -> UsageReport {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("since"), DataValue::Str(since.unwrap_or("").into())),
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ day, account, model, count(at), sum(prompt_tokens), sum(completion_tokens), or(estimated) ] :=
+                *usage { at, day, account, model, prompt_tokens, completion_tokens, estimated },
+                day >= $since
+
+            :sort -day, account, model
+        ", params).unwrap();
+
+        let days = result.rows.into_iter()
+            .filter_map(|row| match &row[..] {
+                [
+                    DataValue::Str(day),
+                    DataValue::Str(account),
+                    DataValue::Str(model),
+                    requests,
+                    prompt_tokens,
+                    completion_tokens,
+                    DataValue::Bool(estimated)
+                ] => Some(Usage {
+                    day: Some(day.to_string()),
+                    account: account.to_string(),
+                    model: model.to_string(),
+                    requests: requests.get_float()? as u64,
+                    prompt_tokens: prompt_tokens.get_float()? as u64,
+                    completion_tokens: completion_tokens.get_float()? as u64,
+                    estimated: *estimated
+                }),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+
+        let mut accounts = Vec::<Usage>::new();
+        for usage in &days {
+            match accounts.iter_mut().find(|a| a.account == usage.account && a.model == usage.model) {
+                Some(total) => {
+                    total.requests += usage.requests;
+                    total.prompt_tokens += usage.prompt_tokens;
+                    total.completion_tokens += usage.completion_tokens;
+                    total.estimated |= usage.estimated;
+                },
+                None => accounts.push(Usage {
+                    day: None,
+                    ..usage.clone()
+                })
+            }
+        }
+
+        UsageReport { days, accounts }
+    }
+
+    /// Caches the description of a folder along with a hash of the listing it describes
+    pub fn set_description(&self, path: &Path, listing: &str, description: &Suggestions) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+            (String::from("listing"), DataValue::Str(listing.into())),
+            (String::from("purpose"), DataValue::Str(description.purpose.clone().into())),
+            (String::from("actions"), DataValue::Str(serde_json::to_string(&description.actions).unwrap().into()))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ path, listing, purpose, actions ] <- [[ $path, $listing, $purpose, $actions ]]
+            :put descriptions { path => listing, purpose, actions }
+        ", params).unwrap();
+    }
+
+    /// The cached description of a folder and the hash of the listing it was made from
+    pub fn get_description(&self, path: &Path) -> Option<(String, Suggestions)> {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ listing, purpose, actions ] := *descriptions { path, listing, purpose, actions }, path == $path
+        ", params).unwrap();
+
+        result.rows.into_iter().next().and_then(|row| match &row[..] {
+            [ DataValue::Str(listing), DataValue::Str(purpose), DataValue::Str(actions) ] => Some((
+                listing.to_string(),
+                Suggestions {
+                    purpose: purpose.to_string(),
+                    actions: serde_json::from_str(actions).ok()?
+                }
+            )),
+            _ => None
+        })
+    }
+
+    /// Records a new operation in the journal. Anything that was undone can't be redone anymore
+    /// once something new has happened, so those entries are dropped, as are the oldest ones past
+    /// `JOURNAL_LIMIT`.
+    pub fn push_operation(&self, operation: &Operation) {
+        self.db.run_script("
+            ?[ id ] := *journal { id, undone: true }
+            :rm journal { id }
+        ", BTreeMap::new()).unwrap();
+
+        let result = self.db.run_script("
+            ?[ max(id) ] := *journal { id }
+        ", BTreeMap::new()).unwrap();
+
+        let id = result.rows.first()
+            .and_then(|row| row.first())
+            .and_then(|id| id.get_int())
+            .unwrap_or(0) + 1;
+
+        self.set_operation(id, operation, false);
+
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("oldest"), DataValue::from(id - JOURNAL_LIMIT))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ id ] := *journal { id }, id <= $oldest
+            :rm journal { id }
+        ", params).unwrap();
+    }
+
+    /// The operation to undo when `undone` is false, otherwise the operation to redo
+    pub fn get_operation(&self, undone: bool) -> Option<(i64, Operation)> {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("undone"), undone.into())
+        ].into_iter().collect();
+
+        let order = if undone { "id" } else { "-id" };
+        let result = self.db.run_script(&format!("
+            ?[ id, operation ] := *journal {{ id, operation, undone }}, undone == $undone
+
+            :sort {order}
+            :limit 1
+        "), params).unwrap();
+
+        result.rows.into_iter().next().and_then(|row| match &row[..] {
+            [ id, DataValue::Str(operation) ] => Some((
+                id.get_int()?,
+                serde_json::from_str(operation).ok()?
+            )),
+            _ => None
+        })
+    }
+
+    pub fn set_operation(&self, id: i64, operation: &Operation, undone: bool) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("id"), DataValue::from(id)),
+            (String::from("operation"), DataValue::Str(serde_json::to_string(operation).unwrap().into())),
+            (String::from("undone"), undone.into())
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ id, operation, undone ] <- [[ $id, $operation, $undone ]]
+            :put journal { id => operation, undone }
+        ", params).unwrap();
+    }
+
+    /// Appends an item to the conversation had about the folder at `path`
+    pub fn add_conversation_item(&self, path: &Path, item: &ConversationItem) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ max(id) ] := *conversations { path, id }, path == $path
+        ", params.clone()).unwrap();
+
+        let id = result.rows.first()
+            .and_then(|row| row.first())
+            .and_then(|id| id.get_int())
+            .unwrap_or(0) + 1;
+
+        let mut params = params;
+        params.insert(String::from("id"), DataValue::from(id));
+        params.insert(String::from("item"), DataValue::Str(serde_json::to_string(item).unwrap().into()));
+
+        self.db.run_script("
+            ?[ path, id, item ] <- [[ $path, $id, $item ]]
+            :put conversations { path, id => item }
+        ", params).unwrap();
+    }
+
+    /// The conversation had about the folder at `path`, oldest first
+    pub fn get_conversation(&self, path: &Path) -> Vec<ConversationItem> {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ id, item ] := *conversations { path, id, item }, path == $path
+
+            :sort id
+        ", params).unwrap();
+
+        result.rows.into_iter()
+            .filter_map(|row| match &row[..] {
+                [ _, DataValue::Str(item) ] => serde_json::from_str(item).ok(),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn clear_conversation(&self, path: &Path) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("path"), DataValue::Str(path.to_str().unwrap().into())),
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ path, id ] := *conversations { path, id }, path == $path
+            :rm conversations { path, id }
+        ", params).unwrap();
+    }
+
     pub fn set_account(&self, account: &Option<Account>) {
         let params = match account {
             None => vec![
@@ -127,7 +762,17 @@ impl Store {
                 ("active", account.active.into()),
                 ("key", DataValue::Str(account.key.clone().into())),
                 ("email", DataValue::Str(account.email.clone().into()))
-            ]
+            ],
+            Some(Account::Local(account)) => {
+                self.set_local_account(account);
+
+                vec![
+                    ("type", DataValue::Str("local".into())),
+                    ("active", true.into()),
+                    ("key", DataValue::Str(account.key.clone().into())),
+                    ("email", DataValue::Str("".into()))
+                ]
+            }
         };
         let params = params.into_iter().map(|(a, b)| (String::from(a), b)).collect::<Vec<_>>();
         let result = self.db.run_script("
@@ -141,7 +786,72 @@ impl Store {
         ", params.into_iter().collect()).unwrap();
     }
 
+    /// The server and model of a local account, kept apart from the other accounts so their
+    /// settings don't change shape
+    fn set_local_account(&self, account: &AccountLocal) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("base_url"), DataValue::Str(account.base_url.clone().into())),
+            (String::from("model"), DataValue::Str(account.model.clone().into()))
+        ].into_iter().collect();
+
+        self.db.run_script("
+            ?[ base_url, model ] <- [[ $base_url, $model ]]
+            :replace local_account {
+                base_url: String,
+                model: String
+            }
+        ", params).unwrap();
+    }
+
+    pub fn set_trash_retention(&self, retention: &TrashRetention) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("max_age_days"), retention.max_age_days
+                .map(|days| DataValue::from(days as i64))
+                .unwrap_or(DataValue::Null)),
+            (String::from("max_size"), retention.max_size
+                .map(|size| DataValue::from(size as i64))
+                .unwrap_or(DataValue::Null))
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ max_age_days, max_size ] <- [[ $max_age_days, $max_size ]]
+            :replace trash_retention {
+                max_age_days: Int?,
+                max_size: Int?
+            }
+        ", params).unwrap();
+    }
+
+    pub fn set_script_policy(&self, policy: &ScriptPolicy) {
+        let params: BTreeMap<String, DataValue> = vec![
+            (String::from("timeout_secs"), DataValue::from(policy.timeout_secs as i64)),
+            (String::from("max_output_bytes"), DataValue::from(policy.max_output_bytes as i64)),
+            (String::from("restrict_writes"), policy.restrict_writes.into()),
+            (String::from("allow_network"), policy.allow_network.into())
+        ].into_iter().collect();
+
+        let result = self.db.run_script("
+            ?[ timeout_secs, max_output_bytes, restrict_writes, allow_network ] <- [[
+                $timeout_secs, $max_output_bytes, $restrict_writes, $allow_network
+            ]]
+            :replace script_policy {
+                timeout_secs: Int,
+                max_output_bytes: Int,
+                restrict_writes: Bool,
+                allow_network: Bool
+            }
+        ", params).unwrap();
+    }
+
     pub fn get_settings(&self) -> Settings {
+        Settings {
+            account: self.get_account(),
+            trash_retention: self.get_trash_retention(),
+            script_policy: self.get_script_policy()
+        }
+    }
+
+    fn get_account(&self) -> Option<Account> {
         let result = self.db.run_script("
             ?[type, active, key, email ] :=
                 *settings { account_type: type, account_active: active, account_key: key, account_email: email }
@@ -155,30 +865,103 @@ impl Store {
                 match &row[..] {
                     [ Str(acc_type), Bool(active), Str(key), Str(email) ] => {
                         match &**acc_type {
-                            "direct" => Settings {
-                                account: Some(Account::Direct(AccountDirect(key.to_string())))
-                            },
-                            "aerome" => Settings {
-                                account: Some(Account::Aerome(AccountAerome {
-                                    active: *active,
-                                    key: key.to_string(),
-                                    email: email.to_string()
-                                }))
-                            },
-                            _ => Settings::default()
+                            "direct" => Some(Account::Direct(AccountDirect(key.to_string()))),
+                            "aerome" => Some(Account::Aerome(AccountAerome {
+                                active: *active,
+                                key: key.to_string(),
+                                email: email.to_string()
+                            })),
+                            "local" => self.get_local_account(key),
+                            _ => None
+                        }
+                    },
+                    _ => None
+                }
+            },
+            Err(_) => None
+        }
+    }
+
+    fn get_local_account(&self, key: &str) -> Option<Account> {
+        let result = self.db.run_script("
+            ?[ base_url, model ] := *local_account { base_url, model }
+        ", BTreeMap::new()).ok()?;
+
+        let row = result.rows.into_iter().next()?;
+        match &row[..] {
+            [ DataValue::Str(base_url), DataValue::Str(model) ] => Some(Account::Local(AccountLocal {
+                base_url: base_url.to_string(),
+                model: model.to_string(),
+                key: key.to_string()
+            })),
+            _ => None
+        }
+    }
+
+    fn get_trash_retention(&self) -> TrashRetention {
+        let result = self.db.run_script("
+            ?[ max_age_days, max_size ] := *trash_retention { max_age_days, max_size }
+        ", BTreeMap::new());
+
+        match result {
+            Ok(result) => match &result.rows.into_iter().next().unwrap_or_default()[..] {
+                [ max_age_days, max_size ] => TrashRetention {
+                    max_age_days: max_age_days.get_int().map(|days| days as u32),
+                    max_size: max_size.get_int().map(|size| size as u64)
+                },
+                _ => TrashRetention::default()
+            },
+            Err(_) => TrashRetention::default()
+        }
+    }
+
+    fn get_script_policy(&self) -> ScriptPolicy {
+        let result = self.db.run_script("
+            ?[ timeout_secs, max_output_bytes, restrict_writes, allow_network ] :=
+                *script_policy { timeout_secs, max_output_bytes, restrict_writes, allow_network }
+        ", BTreeMap::new());
+
+        match result {
+            Ok(result) => {
+                use DataValue::*;
+                match &result.rows.into_iter().next().unwrap_or_default()[..] {
+                    [ timeout_secs, max_output_bytes, Bool(restrict_writes), Bool(allow_network) ] => {
+                        let default = ScriptPolicy::default();
+                        ScriptPolicy {
+                            timeout_secs: timeout_secs.get_int()
+                                .map(|secs| secs as u64)
+                                .unwrap_or(default.timeout_secs),
+                            max_output_bytes: max_output_bytes.get_int()
+                                .map(|bytes| bytes as usize)
+                                .unwrap_or(default.max_output_bytes),
+                            restrict_writes: *restrict_writes,
+                            allow_network: *allow_network
                         }
                     },
-                    _ => Settings::default()
+                    _ => ScriptPolicy::default()
                 }
             },
-            Err(_) => Settings::default()
+            Err(_) => ScriptPolicy::default()
         }
     }
 }
 
+/// How much an action's uses count for, halving every `FRECENCY_HALF_LIFE` seconds since it
+/// was last used
+fn frecency(uses: i64, last_used: i64, now: i64) -> f64 {
+    let age = (now - last_used).max(0) as f64;
+    uses as f64 * 0.5f64.powf(age / FRECENCY_HALF_LIFE)
+}
+
+const FRECENCY_HALF_LIFE: f64 = 14. * 24. * 60. * 60.;
+
+/// How many operations can be undone
+const JOURNAL_LIMIT: i64 = 100;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{ConversationItemFrom,ScriptRisk};
     use serial_test::serial;
 
     #[test]
@@ -188,12 +971,14 @@ mod tests {
         let action = Action {
             code: "some code".into(),
             question: "How's it goin?".into(),
-            description: Some("humho".into())
+            description: Some("humho".into()),
+            ..Default::default()
         };
         let other = Action {
             code: "some code".into(),
             question: "How's it goin?".into(),
-            description: None
+            description: None,
+            ..Default::default()
         };
 
         store.add_suggestion(&PathBuf::from("/foo/bar"), &action);
@@ -202,6 +987,275 @@ mod tests {
         assert_eq!(1, store.get_suggestions(&PathBuf::from("/foo/bar")).actions.len());
     }
 
+    #[test]
+    #[serial]
+    fn manage_suggestions() {
+        let store = Store::new();
+        let path = PathBuf::from("/foo/managed");
+        let action = |code: &str| Action {
+            code: code.into(),
+            question: format!("Run {code}"),
+            description: Some(code.into()),
+            ..Default::default()
+        };
+
+        for code in [ "a", "b", "c", "d", "e" ] {
+            store.delete_suggestion(&path, code);
+        }
+
+        store.add_suggestion(&path, &action("a"));
+        store.add_suggestion(&path, &action("b"));
+        store.add_suggestion(&path, &action("b"));
+        store.add_suggestion(&path, &action("c"));
+        store.use_suggestion(&path, "b");
+        store.use_suggestion(&path, "b");
+        store.use_suggestion(&path, "c");
+        store.pin_suggestion(&path, "a", true);
+
+        let codes = |store: &Store| store.get_suggestions(&path).actions.into_iter()
+            .map(|action| action.code)
+            .collect::<Vec<_>>();
+
+        assert_eq!(codes(&store), vec![ "a", "b", "c" ]);
+
+        store.rename_suggestion(&path, "c", "Renamed");
+        store.edit_suggestion(&path, "b", "d");
+        store.delete_suggestion(&path, "a");
+
+        let actions = store.get_suggestions(&path).actions;
+        assert_eq!(actions.len(), 2);
+        assert_eq!((actions[0].code.as_str(), actions[0].uses), ("d", 2));
+        assert_eq!(actions[1].description.as_deref(), Some("Renamed"));
+    }
+
+    #[test]
+    #[serial]
+    fn scoped_suggestions() {
+        let store = Store::new();
+        let path = PathBuf::from("/foo/scoped");
+        let child = PathBuf::from("/foo/scoped/child");
+        let elsewhere = PathBuf::from("/patterned/photos");
+        let action = |code: &str| Action {
+            code: code.into(),
+            question: format!("Run {code}"),
+            description: Some(code.into()),
+            ..Default::default()
+        };
+
+        for code in [ "a", "b", "c", "d" ] {
+            store.delete_suggestion(&path, code);
+            store.delete_suggestion(&child, code);
+        }
+
+        store.add_suggestion(&path, &action("a"));
+        store.add_suggestion(&path, &action("b"));
+        store.add_suggestion(&path, &action("c"));
+        store.scope_suggestion(&path, "a", &ActionScope::Tree);
+        store.scope_suggestion(&path, "b", &ActionScope::Pattern { glob: "/patterned/*".into() });
+        store.add_suggestion(&child, &action("a"));
+
+        let actions = store.get_suggestions(&child).actions;
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].origin, None);
+
+        let actions = store.get_suggestions(&elsewhere).actions;
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].code, "b");
+        assert_eq!(actions[0].origin, Some(path.clone()));
+        assert_eq!(actions[0].scope, ActionScope::Pattern { glob: "/patterned/*".into() });
+
+        // Uses count towards the folder the action was saved in
+        assert_eq!(store.suggestion_origin(&child, "a"), child);
+        store.use_suggestion(&store.suggestion_origin(&elsewhere, "b"), "b");
+        assert_eq!(store.get_suggestions(&elsewhere).actions[0].uses, 1);
+
+        store.edit_suggestion(&path, "b", "d");
+        assert_eq!(store.get_suggestions(&elsewhere).actions[0].code, "d");
+
+        store.delete_suggestion(&child, "a");
+        store.scope_suggestion(&path, "d", &ActionScope::Folder);
+        assert!(store.get_suggestions(&elsewhere).actions.is_empty());
+        assert_eq!(store.get_suggestions(&child).actions[0].origin, Some(path.clone()));
+    }
+
+    #[test]
+    #[serial]
+    fn import_exported_actions() {
+        let store = Store::new();
+        let path = PathBuf::from("/foo/shared");
+        let action = |code: &str, description: &str| BundledAction {
+            path: path.clone(),
+            code: code.into(),
+            question: format!("Run {code}"),
+            description: description.into(),
+            scope: ActionScope::Tree
+        };
+
+        for code in [ "a", "b", "c" ] {
+            store.delete_suggestion(&path, code);
+        }
+
+        store.import_actions(&[ action("a", "A"), action("b", "B") ], false);
+        assert_eq!(store.export_actions(Some(&path)), vec![ action("a", "A"), action("b", "B") ]);
+        assert!(store.export_actions(Some(Path::new("/foo/shared/child"))).is_empty());
+
+        let bundle = [ action("a", "A"), action("b", "Changed"), action("c", "C") ];
+        let import = store.import_actions(&bundle, false);
+
+        assert_eq!((import.added, import.replaced, import.unchanged), (1, 0, 1));
+        assert_eq!(import.conflicts, vec![ ActionConflict {
+            ours: action("b", "B"),
+            theirs: action("b", "Changed")
+        } ]);
+
+        let import = store.import_actions(&bundle, true);
+        assert_eq!((import.added, import.replaced, import.unchanged), (0, 1, 2));
+        assert_eq!(store.export_actions(Some(&path)), bundle.to_vec());
+    }
+
+    #[test]
+    #[serial]
+    fn usage_per_day_and_account() {
+        let store = Store::new();
+        let account = format!("Test account {}", Utc::now().timestamp_micros());
+        let today = Local::now().format("%Y-%m-%d").to_string();
+
+        store.record_usage("summary.pr", &account, "default", 100, 10, false);
+        store.record_usage("conversation.pr", &account, "default", 200, 20, true);
+        store.record_usage("conversation.pr", &account, "llama3", 50, 5, false);
+
+        let report = store.get_usage(Some(&today));
+        let days = report.days.iter().filter(|usage| usage.account == account).collect::<Vec<_>>();
+        let accounts = report.accounts.iter().filter(|usage| usage.account == account).collect::<Vec<_>>();
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(accounts, vec![
+            &Usage {
+                day: None,
+                account: account.clone(),
+                model: "default".into(),
+                requests: 2,
+                prompt_tokens: 300,
+                completion_tokens: 30,
+                estimated: true
+            },
+            &Usage {
+                day: None,
+                account: account.clone(),
+                model: "llama3".into(),
+                requests: 1,
+                prompt_tokens: 50,
+                completion_tokens: 5,
+                estimated: false
+            }
+        ]);
+
+        assert!(store.get_usage(Some("9999-01-01")).days.is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn description_fills_suggestions() {
+        let store = Store::new();
+        let path = PathBuf::from("/foo/described");
+        let used = Action {
+            code: "pandoc a.md -o a.html".into(),
+            question: "Convert a.md to html".into(),
+            description: Some("Convert to html".into()),
+            ..Default::default()
+        };
+        let candidate = Action {
+            code: "rm *.bak".into(),
+            question: "Remove backups".into(),
+            description: Some("Remove backups".into()),
+            ..Default::default()
+        };
+
+        store.add_suggestion(&path, &used);
+        store.set_description(&path, "abc", &Suggestions {
+            purpose: "Notes written in markdown".into(),
+            actions: vec![ used.clone(), candidate ]
+        });
+
+        let suggestions = store.get_suggestions(&path);
+        assert_eq!(suggestions.purpose, "Notes written in markdown");
+        assert_eq!(suggestions.actions.len(), 2);
+        assert_eq!(suggestions.actions[1].question, "Remove backups");
+        assert_eq!(suggestions.actions[1].safety.as_ref().unwrap().risk, ScriptRisk::Medium);
+        assert_eq!(store.get_description(&path).unwrap().0, "abc");
+    }
+
+    #[test]
+    #[serial]
+    fn journal_undo_redo() {
+        let store = Store::new();
+        let rename = Operation::Rename {
+            from: PathBuf::from("/foo/a"),
+            to: PathBuf::from("/foo/b")
+        };
+        let other = Operation::Move {
+            parent: PathBuf::from("/foo"),
+            names: vec!["c".into()],
+            to: PathBuf::from("/bar")
+        };
+
+        store.push_operation(&rename);
+        let (id, operation) = store.get_operation(false).unwrap();
+        assert_eq!(operation, rename);
+
+        store.set_operation(id, &operation, true);
+        assert_eq!(store.get_operation(true).unwrap(), (id, rename));
+
+        store.push_operation(&other);
+        assert!(store.get_operation(true).is_none());
+        assert_eq!(store.get_operation(false).unwrap().1, other);
+    }
+
+    #[test]
+    #[serial]
+    fn journal_limit() {
+        let store = Store::new();
+        let rename = |i: i64| Operation::Rename {
+            from: PathBuf::from(format!("/foo/{i}")),
+            to: PathBuf::from(format!("/foo/{i}.bak"))
+        };
+
+        for i in 0..JOURNAL_LIMIT + 5 {
+            store.push_operation(&rename(i));
+        }
+
+        let mut undone = 0;
+        while let Some((id, operation)) = store.get_operation(false) {
+            store.set_operation(id, &operation, true);
+            undone += 1;
+        }
+
+        assert_eq!(undone, JOURNAL_LIMIT);
+        assert_eq!(store.get_operation(true).unwrap().1, rename(5));
+    }
+
+    #[test]
+    #[serial]
+    fn conversation_per_folder() {
+        let store = Store::new();
+        let path = PathBuf::from("/foo/bar");
+        let question = ConversationItem::user("Convert the markdown files to html".into());
+        let answer = ConversationItem::new("Sure".into(), Some("pandoc a.md -o a.html".into()));
+
+        store.clear_conversation(&path);
+        store.clear_conversation(&PathBuf::from("/foo/boo"));
+        store.add_conversation_item(&path, &question);
+        store.add_conversation_item(&path, &answer);
+
+        let conversation = store.get_conversation(&path);
+        assert_eq!(conversation, vec![ question, answer ]);
+        assert_eq!(conversation[0].from, ConversationItemFrom::User);
+        assert!(store.get_conversation(&PathBuf::from("/foo/boo")).is_empty());
+
+        store.clear_conversation(&path);
+        assert!(store.get_conversation(&path).is_empty());
+    }
+
     #[test]
     #[serial]
     fn settings_direct_account() {
@@ -211,7 +1265,8 @@ mod tests {
         store.set_account(&Some(direct.clone()));
 
         assert_eq!(store.get_settings(), Settings {
-            account: Some(direct)
+            account: Some(direct),
+            ..Default::default()
         });
     }
 
@@ -228,7 +1283,42 @@ mod tests {
         store.set_account(&Some(aerome.clone()));
 
         assert_eq!(store.get_settings(), Settings {
-            account: Some(aerome)
+            account: Some(aerome),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[serial]
+    fn settings_local_account() {
+        let store = Store::new();
+        let local = Account::Local(AccountLocal {
+            base_url: "http://localhost:11434/v1".into(),
+            model: "llama3".into(),
+            key: "".into()
         });
+
+        store.set_account(&Some(local.clone()));
+
+        assert_eq!(store.get_settings(), Settings {
+            account: Some(local),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[serial]
+    fn settings_trash_retention() {
+        let store = Store::new();
+        let retention = TrashRetention {
+            max_age_days: Some(30),
+            max_size: None
+        };
+
+        store.set_trash_retention(&retention);
+        assert_eq!(store.get_settings().trash_retention, retention);
+
+        store.set_trash_retention(&TrashRetention::default());
+        assert_eq!(store.get_settings().trash_retention, TrashRetention::default());
     }
 }
@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::path::{Component,Path,PathBuf};
+use crate::shell::commands;
+
+/// Checks that a script only refers to the `targets` among the `names` in `dir`, returning the
+/// other names and any paths outside of `dir` it refers to otherwise. This only looks at the words
+/// in the script, what variables and command substitutions expand to can't be known without
+/// running it.
+pub fn validate_targets(
+    script: &str,
+    dir: &Path,
+    names: &[String],
+    targets: &[String]) -> Result<(), Vec<String>>
+{
+    let mut touched = vec![];
+
+    // The first word is the program being run, `/usr/bin/env` isn't a target
+    let words = commands(script).into_iter().flat_map(|command| command.words.into_iter().skip(1));
+
+    for word in words {
+        let matches = match first_component(&word, dir) {
+            Some(Reference::Folder) => names.to_vec(),
+            Some(Reference::Name(name)) if is_glob(&name) => names.iter()
+                .filter(|candidate| glob_matches(&name, candidate))
+                .cloned()
+                .collect(),
+            Some(Reference::Name(name)) => names.iter()
+                .filter(|candidate| **candidate == name)
+                .cloned()
+                .collect(),
+            Some(Reference::Outside) => vec![word],
+            None => vec![]
+        };
+
+        for name in matches {
+            if !targets.contains(&name) && !touched.contains(&name) {
+                touched.push(name);
+            }
+        }
+    }
+
+    if touched.is_empty() {
+        Ok(())
+    } else {
+        Err(touched)
+    }
+}
+
+enum Reference {
+    /// The folder itself, like the "." in `find . -delete`
+    Folder,
+    Name(String),
+    /// Somewhere outside of the folder, like `../x`, `~/x` or `/etc/hosts`
+    Outside
+}
+
+/// The name of the entry in `dir` that a word refers to, if it looks like a path in it
+fn first_component(word: &str, dir: &Path) -> Option<Reference> {
+    let path = match home_relative(word) {
+        Some(rest) => match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => return Some(Reference::Outside)
+        },
+        None if word.starts_with('~') => return Some(Reference::Outside),
+        None => PathBuf::from(word)
+    };
+
+    let relative = if path.is_absolute() {
+        match path.strip_prefix(dir) {
+            Ok(relative) => relative,
+            Err(_) if path.starts_with("/dev") => return None,
+            Err(_) => return Some(Reference::Outside)
+        }
+    } else {
+        path.as_path()
+    };
+
+    // `photos/../notes.txt` is still in the folder, `photos/../../notes.txt` isn't
+    let mut names = vec![];
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => names.push(name),
+            Component::ParentDir if names.pop().is_none() => return Some(Reference::Outside),
+            _ => {}
+        }
+    }
+
+    match names.first() {
+        Some(name) => Some(Reference::Name(name.to_string_lossy().into_owned())),
+        None if !word.is_empty() && (word.starts_with('.') || path.is_absolute()) => {
+            Some(Reference::Folder)
+        },
+        None => None
+    }
+}
+
+/// What follows the home folder in words like `~/x` or `$HOME/x`
+fn home_relative(word: &str) -> Option<&str> {
+    ["~", "$HOME", "${HOME}"].iter()
+        .filter_map(|home| word.strip_prefix(home))
+        .find(|rest| rest.is_empty() || rest.starts_with('/'))
+        .map(|rest| rest.trim_start_matches('/'))
+}
+
+fn is_glob(name: &str) -> bool {
+    name.contains(|c| c == '*' || c == '?' || c == '[')
+}
+
+/// Matches `*` and `?` wildcards, brackets are treated as matching any single character
+pub(crate) fn glob_matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|i| matches(&pattern[1..], &name[i..])),
+            Some('[') => match pattern.iter().position(|c| *c == ']') {
+                Some(end) => !name.is_empty() && matches(&pattern[end + 1..], &name[1..]),
+                None => name.first() == Some(&'[') && matches(&pattern[1..], &name[1..])
+            },
+            Some('?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+            Some(c) => name.first() == Some(c) && matches(&pattern[1..], &name[1..])
+        }
+    }
+
+    // Hidden files aren't matched by wildcards in bash
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    matches(&pattern, &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec![ "a.jpg".into(), "b.jpg".into(), "notes.txt".into(), "photos".into() ]
+    }
+
+    #[test]
+    fn targets_only_selected() {
+        let dir = Path::new("/home/user/pictures");
+        let targets = vec![ "a.jpg".to_string(), "b.jpg".to_string() ];
+
+        let script = r#"
+            # Rename a.jpg and b.jpg by date
+            for f in "a.jpg" './b.jpg'; do
+                mv "$f" "$(date +%F)_$f"
+            done
+            zip /home/user/pictures/archive.zip a.jpg
+        "#;
+        assert!(validate_targets(script, dir, &names(), &targets).is_ok());
+
+        assert_eq!(validate_targets("rm *.jpg notes.txt", dir, &names(), &targets),
+            Err(vec![ "notes.txt".into() ]));
+        assert_eq!(validate_targets("mv photos/x.png .", dir, &names(), &targets),
+            Err(vec![ "photos".into(), "notes.txt".into() ]));
+        assert_eq!(validate_targets("rm /home/user/pictures/*", dir, &names(), &targets),
+            Err(vec![ "notes.txt".into(), "photos".into() ]));
+    }
+
+    #[test]
+    fn targets_outside_folder() {
+        let dir = Path::new("/home/user/pictures");
+        let targets = vec![ "a.jpg".to_string() ];
+
+        let script = "/usr/bin/env mv photos/../a.jpg x.jpg 2>/dev/null";
+        assert!(validate_targets(script, dir, &names(), &targets).is_ok());
+        assert_eq!(validate_targets("rm -rf ../x a.jpg", dir, &names(), &targets),
+            Err(vec![ "../x".into() ]));
+        assert_eq!(validate_targets("mv a.jpg photos/../../x", dir, &names(), &targets),
+            Err(vec![ "photos/../../x".into() ]));
+        assert_eq!(validate_targets("mv a.jpg ~/x", dir, &names(), &targets),
+            Err(vec![ "~/x".into() ]));
+        assert_eq!(validate_targets("cp a.jpg \"$HOME\" ~other/x", dir, &names(), &targets),
+            Err(vec![ "$HOME".into(), "~other/x".into() ]));
+        assert_eq!(validate_targets("cat a.jpg > /etc/hosts", dir, &names(), &targets),
+            Err(vec![ "/etc/hosts".into() ]));
+    }
+}
@@ -0,0 +1,174 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::models::{ActionArgument,ActionParameter,ActionParameterKind};
+
+/// The parameters an action's code asks for, in the order their placeholders first appear
+pub fn parameters(code: &str) -> Vec<ActionParameter> {
+    let mut parameters: Vec<ActionParameter> = vec![];
+
+    for (_, parameter) in placeholders(code) {
+        if !parameters.iter().any(|p| p.name == parameter.name) {
+            parameters.push(parameter);
+        }
+    }
+
+    parameters
+}
+
+/// Fills in the placeholders in `code` with the `arguments` given for them by name. Each value
+/// is shell quoted so it's only ever taken as a single word, which means placeholders shouldn't
+/// be quoted in the code themselves.
+pub fn fill(code: &str, arguments: &HashMap<String, ActionArgument>) -> Result<String, String> {
+    let mut filled = String::with_capacity(code.len());
+    let mut rest = 0;
+
+    for (range, parameter) in placeholders(code) {
+        filled.push_str(&code[rest..range.start]);
+        filled.push_str(&argument(&parameter, arguments.get(&parameter.name))?);
+        rest = range.end;
+    }
+
+    filled.push_str(&code[rest..]);
+    Ok(filled)
+}
+
+fn argument(parameter: &ActionParameter, argument: Option<&ActionArgument>) -> Result<String, String> {
+    use ActionArgument::*;
+    use ActionParameterKind::*;
+
+    match (parameter.kind, argument) {
+        (Files, Some(Many(names))) if !names.is_empty() => Ok(names.iter()
+            .map(|name| quote_file(name))
+            .collect::<Vec<_>>()
+            .join(" ")),
+        (Files, Some(One(name))) if !name.is_empty() => Ok(quote_file(name)),
+        (Number, Some(One(number))) => {
+            let number = number.trim();
+            if is_number(number) {
+                Ok(number.to_string())
+            } else {
+                Err(format!("{} should be a number, not \"{number}\"", parameter.name))
+            }
+        },
+        (Folder, Some(One(folder))) if !folder.is_empty() => Ok(quote_file(folder)),
+        (Text, Some(One(text))) => Ok(quote(text)),
+        _ => Err(format!("Nothing was given for {}", parameter.name))
+    }
+}
+
+/// Every `{{kind}}` and `{{kind:name}}` placeholder in `code` and where it is. Anything else
+/// between double braces is left alone, so brace expansions and the like still work.
+fn placeholders(code: &str) -> Vec<(Range<usize>, ActionParameter)> {
+    let mut placeholders = vec![];
+    let mut from = 0;
+
+    while let Some(start) = code[from..].find("{{").map(|i| from + i) {
+        let inner = start + 2;
+        let end = match code[inner..].find("}}") {
+            Some(i) => inner + i,
+            None => break
+        };
+
+        match parse_placeholder(&code[inner..end]) {
+            Some(parameter) => {
+                placeholders.push((start..end + 2, parameter));
+                from = end + 2;
+            },
+            None => from = inner
+        }
+    }
+
+    placeholders
+}
+
+fn parse_placeholder(text: &str) -> Option<ActionParameter> {
+    let (kind, name) = match text.split_once(':') {
+        Some((kind, name)) => (kind.trim(), name.trim()),
+        None => (text.trim(), text.trim())
+    };
+
+    let kind = match kind {
+        "files" => ActionParameterKind::Files,
+        "folder" => ActionParameterKind::Folder,
+        "text" => ActionParameterKind::Text,
+        "number" => ActionParameterKind::Number,
+        _ => return None
+    };
+
+    if name.is_empty() || name.contains(|c| c == '{' || c == '}' || c == '\n') {
+        return None;
+    }
+
+    Some(ActionParameter { name: name.to_string(), kind })
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Quotes a path, making sure one that starts with a dash isn't taken for an option
+fn quote_file(path: &str) -> String {
+    if path.starts_with('-') {
+        quote(&format!("./{path}"))
+    } else {
+        quote(path)
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    text.parse::<f64>().map(f64::is_finite).unwrap_or(false)
+        && text.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-' || c == '+')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_parameters() {
+        let code = "mkdir -p {{folder:Destination}}\n\
+            for f in {{files}}; do convert \"$f\" -resize {{number:Width}} {{folder:Destination}}/\"$f\"; done\n\
+            echo {a,b}{{c,d}} {{unknown}}";
+
+        assert_eq!(parameters(code), vec![
+            ActionParameter { name: "Destination".into(), kind: ActionParameterKind::Folder },
+            ActionParameter { name: "files".into(), kind: ActionParameterKind::Files },
+            ActionParameter { name: "Width".into(), kind: ActionParameterKind::Number }
+        ]);
+    }
+
+    #[test]
+    fn template_fill() {
+        let code = "convert {{files}} -resize {{number:Width}} {{text:Suffix}} {{c,d}}";
+        let mut arguments = HashMap::from([
+            ("files".to_string(), ActionArgument::Many(vec![ "a b.jpg".into(), "-c'.jpg".into() ])),
+            ("Width".to_string(), ActionArgument::One(" 800 ".into())),
+            ("Suffix".to_string(), ActionArgument::One("$(rm -rf ~)".into()))
+        ]);
+
+        assert_eq!(fill(code, &arguments), Ok(
+            r"convert 'a b.jpg' './-c'\''.jpg' -resize 800 '$(rm -rf ~)' {{c,d}}".to_string()));
+
+        arguments.insert("Width".into(), ActionArgument::One("800; rm -rf ~".into()));
+        assert!(fill(code, &arguments).is_err());
+
+        arguments.remove("files");
+        assert_eq!(fill(code, &arguments), Err("Nothing was given for files".to_string()));
+    }
+}
@@ -15,77 +15,357 @@
  */
 
 use std::fs;
-use std::process::Command;
+use std::io::{self,Write};
+use std::collections::BTreeMap;
+use std::process::{Command,Stdio};
 use std::path::{PathBuf,Path};
-use chrono::{DateTime,Utc,TimeZone};
+use std::os::unix::fs::{MetadataExt,PermissionsExt,DirBuilderExt};
+use std::os::unix::ffi::{OsStrExt,OsStringExt};
+use std::ffi::OsString;
+use std::fmt;
+use chrono::{DateTime,Local,NaiveDateTime,TimeZone,Timelike};
+use derive_more::{From,Error,Display};
 use dirs;
 use crate::constants::APP_NAME;
+use crate::models::{TrashExpiry,TrashUsage,TrashItemUsage,TrashRestoreConflict,TrashRetention};
+use crate::ipc::FileTransferCmdResponse;
+use fs_extra::dir::CopyOptions;
 
+#[derive(Clone)]
 pub struct Trash {}
 
+/// A single trash directory. The home trash lives in `$XDG_DATA_HOME/Trash`, every other volume
+/// gets its own trash at `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashDir {
+    pub files: PathBuf,
+    pub info: PathBuf,
+    /// The mount point this trash belongs to. `None` for the home trash, whose `Path=` entries are
+    /// absolute, while volume trashes store paths relative to their topdir.
+    pub topdir: Option<PathBuf>
+}
+
+impl TrashDir {
+    fn new(base: PathBuf, topdir: Option<PathBuf>) -> Self {
+        Self {
+            files: base.join("files"),
+            info: base.join("info"),
+            topdir
+        }
+    }
+
+    fn create(&self) -> io::Result<()> {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(&self.files)?;
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(&self.info)
+    }
+
+    fn original_path(&self, path: &Path) -> PathBuf {
+        match &self.topdir {
+            Some(topdir) if path.is_relative() => topdir.join(path),
+            _ => path.to_path_buf()
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.info_path(name).exists()
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.info.join(format!("{name}.trashinfo"))
+    }
+
+    fn read_info(&self, name: &str) -> Result<TrashInfo, TrashInfoError> {
+        TrashInfo::parse(&fs::read_to_string(self.info_path(name))?)
+    }
+
+    fn directory_sizes(&self) -> PathBuf {
+        self.files.with_file_name("directorysizes")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrashItem {
+    /// The name of the item in the trash, which is what `restore` and `clear` take
+    pub name: String,
+    pub path: PathBuf,
+    pub original: Option<PathBuf>,
+    pub deletion_date: Option<DateTime<Local>>,
+    pub size: u64
+}
+
+impl Trash {
+    pub fn home_dir() -> TrashDir {
+        TrashDir::new(dirs::data_dir().unwrap().join("Trash"), None)
+    }
+}
+
 #[cfg(target_os = "linux")]
 impl Trash {
     // https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
-    // This is a piss poor implementation of the above spec, but will do for now
 
     pub fn new() -> Trash {
         Trash {}
     }
 
-    pub fn put(&self, paths: &[PathBuf]) {
-        let (files, info) = get_trash_dirs();
-        let now: DateTime<Utc> = Utc::now();
-        let timestamp = now.format("%Y-%m-%dT%H:%M:%S").to_string();
+    /// Every trash directory that currently exists, the home trash first followed by the trash
+    /// directories of each mounted volume.
+    pub fn dirs(&self) -> Vec<TrashDir> {
+        let uid = current_uid();
+        let mut dirs = vec![Self::home_dir()];
 
-        for path in paths {
-            if !path.is_absolute() {
-                panic!(r#"Only absolute paths are supported, was passed "{:?}""#, path);
+        for topdir in mount_points() {
+            let admin = topdir.join(".Trash");
+            let candidates = [
+                admin_trash_is_valid(&admin).then(|| admin.join(uid.to_string())),
+                Some(topdir.join(format!(".Trash-{uid}")))
+            ];
+
+            for base in candidates.into_iter().flatten() {
+                let dir = TrashDir::new(base, Some(topdir.clone()));
+                if dir.files.is_dir() && !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
             }
         }
 
+        dirs
+    }
+
+    /// Moves `paths` into the trash, returning each item that was trashed along with the name it
+    /// was given in the trash. Nothing is trashed unless every path is absolute.
+    pub fn put(&self, paths: &[PathBuf]) -> io::Result<Vec<(PathBuf, String)>> {
+        let mut trashed = vec![];
+
+        if let Some(path) = paths.iter().find(|path| !path.is_absolute()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Only absolute paths can be trashed, was passed {path:?}")));
+        }
+
         for path in paths {
-            let (trashinfo, trashpath) = find_unique_path(&path);
-            let meta = TRASH_META_TEMPLATE
-                .replace("$PATH", &path.to_str().unwrap())
-                .replace("$DATE", &timestamp);
+            // Resolve the parent so the path can be matched against the mount points, which are
+            // always canonical
+            let path = path.parent()
+                .and_then(|parent| parent.canonicalize().ok())
+                .map(|parent| parent.join(path.file_name().unwrap()))
+                .unwrap_or_else(|| path.clone());
+
+            let dir = match find_trash_dir(&path) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    log::error!("Could not find a trash directory for {path:?}: {e:?}");
+                    continue;
+                }
+            };
+
+            let (trashinfo, trashpath) = find_unique_path(&self.dirs(), &dir, &path);
+            let original = match &dir.topdir {
+                Some(topdir) => path.strip_prefix(topdir).unwrap_or(&path),
+                None => &path
+            };
+            let info = TrashInfo::new(original.to_path_buf());
+
+            // Creating the info file exclusively is what reserves the name in the trash
+            let written = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&trashinfo)
+                .and_then(|mut file| file.write_all(info.to_string().as_bytes()));
+
+            if let Err(e) = written {
+                log::error!("Could not write {trashinfo:?}: {e:?}");
+                continue;
+            }
+
+            if let Err(e) = fs::rename(&path, &trashpath) {
+                log::error!("Could not move {path:?} to the trash: {e:?}");
+                let _ = fs::remove_file(&trashinfo);
+                continue;
+            }
+
+            let name = trashpath.file_name().unwrap().to_str().unwrap().to_string();
+
+            if trashpath.is_dir() {
+                let mut sizes = DirectorySizes::load(&dir);
+                sizes.insert(&dir, &name);
+                sizes.save(&dir);
+            }
+
+            trashed.push((path, name));
+        }
+
+        Ok(trashed)
+    }
+
+    /// Everything in every trash along with what its `.trashinfo` says about it. Directories are
+    /// sized through each trash's `directorysizes` cache so multi gigabyte trees don't have to be
+    /// walked every time.
+    pub fn list(&self) -> Vec<TrashItem> {
+        let mut items = vec![];
+
+        for dir in self.dirs() {
+            let mut sizes = DirectorySizes::load(&dir);
+            let entries = fs::read_dir(&dir.files).into_iter().flatten().filter_map(|e| e.ok());
+
+            for entry in entries {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let size = match entry.file_type() {
+                    Ok(kind) if kind.is_dir() => sizes.get(&dir, &name),
+                    _ => disk_usage(&entry.path())
+                };
+                let info = dir.read_info(&name).ok();
+
+                items.push(TrashItem {
+                    path: entry.path(),
+                    original: info.as_ref().map(|info| dir.original_path(&info.path)),
+                    deletion_date: info.map(|info| info.deletion_date),
+                    name,
+                    size
+                });
+            }
 
-            fs::write(trashinfo, &meta).unwrap();
-            fs::rename(path, trashpath).unwrap();
+            sizes.save(&dir);
         }
+
+        items
     }
 
-    pub fn restore(&self, paths: &[String]) {
-        let (files, info) = get_trash_dirs();
+    pub fn usage(&self) -> TrashUsage {
+        let items = self.list().into_iter()
+            .map(|item| TrashItemUsage { name: item.name, size: item.size })
+            .collect::<Vec<_>>();
+
+        TrashUsage {
+            total: items.iter().map(|item| item.size).sum(),
+            items
+        }
+    }
+
+    /// Purges whatever falls outside of the retention policy. Items older than the maximum age
+    /// go first, then the oldest of the rest until the trash fits within the maximum size.
+    pub fn expire(&self, retention: &TrashRetention) -> TrashExpiry {
+        let now = Local::now();
+        let mut items = self.list();
+
+        // Items without a deletion date sort first, they're the ones we know the least about
+        items.sort_by_key(|item| item.deletion_date);
+
+        let mut total: u64 = items.iter().map(|item| item.size).sum();
+        let mut expired = vec![];
+
+        for item in items {
+            let too_old = match (retention.max_age_days, item.deletion_date) {
+                (Some(days), Some(date)) => now - date > chrono::Duration::days(days as i64),
+                _ => false
+            };
+            let too_big = retention.max_size.map(|max| total > max).unwrap_or(false);
+
+            if too_old || too_big {
+                total -= item.size;
+                expired.push(TrashItemUsage { name: item.name, size: item.size });
+            }
+        }
+
+        if !expired.is_empty() {
+            let names = expired.iter().map(|item| item.name.clone()).collect::<Vec<_>>();
+            if let Err(e) = self.clear(Some(&names)) {
+                log::error!("Could not clear expired items from the trash: {e:?}");
+            }
+        }
+
+        TrashExpiry {
+            freed: expired.iter().map(|item| item.size).sum(),
+            items: expired
+        }
+    }
+
+    /// Restores items to where they were trashed from, or into the `to` folder when given. Items
+    /// whose destination is already taken are left in the trash and handed back as conflicts,
+    /// unless `response` says to overwrite or skip them. Restoring stops at the first item that
+    /// can't be moved back.
+    pub fn restore(
+        &self,
+        paths: &[String],
+        to: Option<&Path>,
+        response: &FileTransferCmdResponse) -> io::Result<Vec<TrashRestoreConflict>>
+    {
+        let dirs = self.dirs();
+        let mut conflicts = vec![];
 
         for path in paths {
-            let meta = fs::read_to_string(info.join(format!("{path}.trashinfo"))).unwrap();
-            let restore_path = meta.lines()
-                .find(|line| line.starts_with("Path="))
-                .map(|line| PathBuf::from(line.split('=').last().unwrap()));
+            let Some(dir) = dirs.iter().find(|dir| dir.contains(path)) else {
+                log::error!("{path} was not found in any trash directory");
+                continue;
+            };
+
+            let info = match dir.read_info(path) {
+                Ok(info) => info,
+                Err(e) => {
+                    log::error!("Could not read the trash info for {path}: {e:?}");
+                    continue;
+                }
+            };
+
+            let original = dir.original_path(&info.path);
+            let destination = match (to, original.file_name()) {
+                (Some(to), Some(name)) => to.join(name),
+                _ => original
+            };
 
-            fs::rename(files.join(path), restore_path.unwrap()).unwrap();
-            fs::remove_file(info.join(format!("{path}.trashinfo"))).unwrap();
+            if let Some(conflict) = restore_item(dir, path, &destination, response)? {
+                conflicts.push(conflict);
+            }
         }
+
+        Ok(conflicts)
     }
 
-    pub fn clear(&self, paths: Option<&[String]>) {
-        let (files, info) = get_trash_dirs();
+    /// Restores a single item to exactly `to`, which is how a conflict gets resolved by renaming
+    pub fn restore_as(
+        &self,
+        name: &str,
+        to: &Path,
+        response: &FileTransferCmdResponse) -> io::Result<Option<TrashRestoreConflict>>
+    {
+        match self.dirs().iter().find(|dir| dir.contains(name)) {
+            Some(dir) => restore_item(dir, name, to, response),
+            None => {
+                log::error!("{name} was not found in any trash directory");
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn clear(&self, paths: Option<&[String]>) -> io::Result<()> {
+        let dirs = self.dirs();
 
         match paths {
             Some(paths) => {
                 for path in paths {
-                    fs::remove_file(info.join(format!("{path}.trashinfo"))).unwrap();
-                    fs::remove_file(files.join(path)).unwrap();
+                    let Some(dir) = dirs.iter().find(|dir| dir.contains(path)) else {
+                        log::error!("{path} was not found in any trash directory");
+                        continue;
+                    };
+
+                    fs::remove_file(dir.info_path(path))?;
+                    remove_path(&dir.files.join(path))?;
+
+                    let mut sizes = DirectorySizes::load(dir);
+                    sizes.remove(path);
+                    sizes.save(dir);
                 }
             },
             None => {
-                fs::remove_dir_all(&files).unwrap();
-                fs::remove_dir_all(&info).unwrap();
+                for dir in dirs {
+                    let _ = fs::remove_dir_all(&dir.files);
+                    let _ = fs::remove_dir_all(&dir.info);
+                    let _ = fs::remove_file(dir.directory_sizes());
 
-                fs::create_dir_all(&files).unwrap();
-                fs::create_dir_all(&info).unwrap();
+                    dir.create()?;
+                }
             }
         }
+
+        Ok(())
     }
 }
 
@@ -102,27 +382,392 @@ impl Trash {
         Trash {}
     }
 
-    pub fn put(&self, paths: &[PathBuf]) {
+    pub fn dirs(&self) -> Vec<TrashDir> {
+        vec![Self::home_dir()]
+    }
+
+    pub fn put(&self, paths: &[PathBuf]) -> io::Result<Vec<(PathBuf, String)>> {
         let scripts_dir = dirs::data_local_dir().unwrap().join(APP_NAME).join("scripts");
 
         Command::new(scripts_dir.join("trash.sh"))
             .args(paths)
             .stdin(Stdio::null())
             .stdout(Stdio::null())
-            .spawn()
-            .unwrap();
+            .spawn()?;
+
+        // Items put in the trash by Finder can't be pulled back out, so there's nothing to report
+        Ok(vec![])
+    }
+
+    pub fn list(&self) -> Vec<TrashItem> { vec![] }
+    pub fn usage(&self) -> TrashUsage { TrashUsage::default() }
+    pub fn expire(&self, retention: &TrashRetention) -> TrashExpiry { TrashExpiry::default() }
+    pub fn restore(
+        &self,
+        paths: &[String],
+        to: Option<&Path>,
+        response: &FileTransferCmdResponse) -> io::Result<Vec<TrashRestoreConflict>> { Ok(vec![]) }
+    pub fn restore_as(
+        &self,
+        name: &str,
+        to: &Path,
+        response: &FileTransferCmdResponse) -> io::Result<Option<TrashRestoreConflict>> { Ok(None) }
+    pub fn clear(&self, paths: Option<&[String]>) -> io::Result<()> { Ok(()) }
+}
+
+/// The contents of a `.trashinfo` file. `path` is exactly what's stored in the file, so it's
+/// relative to the topdir for items in a volume trash.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashInfo {
+    pub path: PathBuf,
+    pub deletion_date: DateTime<Local>
+}
+
+#[derive(Display, Debug, From, Error)]
+pub enum TrashInfoError {
+    IO(io::Error),
+    DeletionDate(chrono::ParseError),
+    #[from(ignore)]
+    MissingHeader,
+    #[from(ignore)]
+    MissingPath,
+    #[from(ignore)]
+    MissingDeletionDate,
+    #[from(ignore)]
+    InvalidDeletionDate
+}
+
+impl TrashInfo {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            deletion_date: Local::now().with_nanosecond(0).unwrap()
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, TrashInfoError> {
+        let mut lines = contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        if lines.next() != Some(TRASH_INFO_HEADER) {
+            return Err(TrashInfoError::MissingHeader);
+        }
+
+        let mut path = None;
+        let mut deletion_date = None;
+
+        // Only the first occurrence of a key counts and anything after the next group header
+        // belongs to somebody else
+        for line in lines.take_while(|line| !line.starts_with('[')) {
+            match line.split_once('=') {
+                Some(("Path", value)) if path.is_none() => {
+                    path = Some(PathBuf::from(OsString::from_vec(percent_decode(value))));
+                },
+                Some(("DeletionDate", value)) if deletion_date.is_none() => {
+                    let date = NaiveDateTime::parse_from_str(value, TRASH_INFO_DATE_FORMAT)?;
+                    deletion_date = Some(Local.from_local_datetime(&date)
+                        .earliest()
+                        .ok_or(TrashInfoError::InvalidDeletionDate)?);
+                },
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            path: path.ok_or(TrashInfoError::MissingPath)?,
+            deletion_date: deletion_date.ok_or(TrashInfoError::MissingDeletionDate)?
+        })
     }
+}
+
+impl fmt::Display for TrashInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{TRASH_INFO_HEADER}")?;
+        writeln!(f, "Path={}", percent_encode(self.path.as_os_str().as_bytes()))?;
+        writeln!(f, "DeletionDate={}", self.deletion_date.format(TRASH_INFO_DATE_FORMAT))
+    }
+}
+
+/// The `$trash/directorysizes` cache. Each line is `size mtime name`, where the mtime is that of
+/// the item's `.trashinfo` file and is used to detect stale entries.
+struct DirectorySizes {
+    entries: BTreeMap<String, DirectorySize>,
+    dirty: bool
+}
+
+struct DirectorySize {
+    size: u64,
+    mtime: i64
+}
+
+impl DirectorySizes {
+    fn load(dir: &TrashDir) -> Self {
+        let contents = fs::read_to_string(dir.directory_sizes()).unwrap_or_default();
+        let entries = contents.lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ' ');
+                let size = parts.next()?.parse().ok()?;
+                let mtime = parts.next()?.parse().ok()?;
+                let name = String::from_utf8(percent_decode(parts.next()?)).ok()?;
+
+                Some((name, DirectorySize { size, mtime }))
+            })
+            .collect();
+
+        Self { entries, dirty: false }
+    }
+
+    fn get(&mut self, dir: &TrashDir, name: &str) -> u64 {
+        match (self.entries.get(name), trashinfo_mtime(dir, name)) {
+            (Some(entry), Some(mtime)) if entry.mtime == mtime => entry.size,
+            _ => self.insert(dir, name)
+        }
+    }
+
+    fn insert(&mut self, dir: &TrashDir, name: &str) -> u64 {
+        let size = disk_usage(&dir.files.join(name));
+        let mtime = trashinfo_mtime(dir, name).unwrap_or_default();
+
+        self.entries.insert(name.to_string(), DirectorySize { size, mtime });
+        self.dirty = true;
+        size
+    }
+
+    fn remove(&mut self, name: &str) {
+        self.dirty |= self.entries.remove(name).is_some();
+    }
+
+    /// The spec requires the cache to be replaced atomically, so it's written out to a temporary
+    /// file first and then renamed over the old one
+    fn save(&self, dir: &TrashDir) {
+        if !self.dirty {
+            return;
+        }
+
+        let path = dir.directory_sizes();
+        let temporary = path.with_extension(format!("{}", std::process::id()));
+        let result = fs::File::create(&temporary)
+            .and_then(|mut file| {
+                for (name, entry) in &self.entries {
+                    writeln!(file, "{} {} {}", entry.size, entry.mtime, percent_encode(name.as_bytes()))?;
+                }
+                Ok(())
+            })
+            .and_then(|_| fs::rename(&temporary, &path));
+
+        if let Err(e) = result {
+            log::error!("Could not update {path:?}: {e:?}");
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+fn trashinfo_mtime(dir: &TrashDir, name: &str) -> Option<i64> {
+    fs::metadata(dir.info_path(name)).ok().map(|meta| meta.mtime())
+}
+
+/// Bytes used on disk, the same figure `du -B1` would report
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+
+    let children = if meta.is_dir() {
+        fs::read_dir(path).into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| disk_usage(&entry.path()))
+            .sum()
+    } else {
+        0
+    };
+
+    meta.blocks() * 512 + children
+}
+
+/// Escapes everything but the unreserved characters of RFC 2396 and `/`
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' |
+            b'-' | b'_' | b'.' | b'!' | b'~' | b'*' | b'\'' | b'(' | b')' | b'/' => {
+                encoded.push(*byte as char);
+            },
+            _ => encoded.push_str(&format!("%{byte:02X}"))
+        }
+    }
+
+    encoded
+}
+
+fn percent_decode(encoded: &str) -> Vec<u8> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+
+        match hex {
+            Some(byte) => { decoded.push(byte); i += 3; },
+            None => { decoded.push(bytes[i]); i += 1; }
+        }
+    }
+
+    decoded
+}
+
+/// Picks the trash directory an item should be moved into. Items on the same device as the home
+/// trash go there, everything else goes to the trash of the volume it lives on so the move is
+/// always a cheap rename instead of failing with EXDEV.
+fn find_trash_dir(path: &Path) -> io::Result<TrashDir> {
+    let home = Trash::home_dir();
+    home.create()?;
+
+    let parent = path.parent().unwrap_or(path);
+    if fs::metadata(parent)?.dev() == fs::metadata(&home.files)?.dev() {
+        return Ok(home);
+    }
+
+    let topdir = mount_points().into_iter()
+        .filter(|mount| parent.starts_with(mount))
+        .max_by_key(|mount| mount.as_os_str().len())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No mount point found"))?;
+    let uid = current_uid();
 
-    pub fn restore(&self, paths: &[String]) {}
-    pub fn clear(&self, paths: Option<&[String]>) {}
+    let admin = topdir.join(".Trash");
+    if admin_trash_is_valid(&admin) {
+        let dir = TrashDir::new(admin.join(uid.to_string()), Some(topdir.clone()));
+        if dir.create().is_ok() {
+            return Ok(dir);
+        }
+    }
+
+    let dir = TrashDir::new(topdir.join(format!(".Trash-{uid}")), Some(topdir));
+    dir.create()?;
+    Ok(dir)
+}
+
+/// `$topdir/.Trash` may only be used when it's a real directory with the sticky bit set,
+/// otherwise other users could swap out our trash from under us.
+fn admin_trash_is_valid(admin: &Path) -> bool {
+    match fs::symlink_metadata(admin) {
+        Ok(meta) => meta.is_dir() && meta.permissions().mode() & 0o1000 != 0,
+        Err(_) => false
+    }
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
 }
 
-fn find_unique_path(path: &Path) -> (PathBuf, PathBuf) {
-    let (files, info) = get_trash_dirs();
+fn mount_points() -> Vec<PathBuf> {
+    fs::read("/proc/self/mounts")
+        .unwrap_or_default()
+        .split(|byte| *byte == b'\n')
+        .filter_map(|line| line.split(|byte| *byte == b' ').nth(1))
+        .map(|mount| PathBuf::from(OsString::from_vec(unescape_mount_point(mount))))
+        .collect()
+}
 
+/// Spaces, tabs, newlines and backslashes are octal escaped in `/proc/self/mounts`
+fn unescape_mount_point(mount: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(mount.len());
+    let mut i = 0;
+
+    while i < mount.len() {
+        let octal = mount.get(i + 1..i + 4)
+            .filter(|_| mount[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+
+        match octal {
+            Some(byte) => { unescaped.push(byte); i += 4; },
+            None => { unescaped.push(mount[i]); i += 1; }
+        }
+    }
+
+    unescaped
+}
+
+fn restore_item(
+    dir: &TrashDir,
+    name: &str,
+    destination: &Path,
+    response: &FileTransferCmdResponse) -> io::Result<Option<TrashRestoreConflict>>
+{
+    use FileTransferCmdResponse::*;
+
+    if fs::symlink_metadata(destination).is_ok() {
+        match response {
+            Overwrite | OverwriteAll => remove_path(destination)?,
+            Skip | SkipAll => return Ok(None),
+            _ => return Ok(Some(TrashRestoreConflict {
+                name: name.to_string(),
+                to: destination.to_path_buf()
+            }))
+        }
+    }
+
+    // The folder the item came from may have been deleted since
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(&dir.files.join(name), destination)?;
+
+    let _ = fs::remove_file(dir.info_path(name));
+
+    let mut sizes = DirectorySizes::load(dir);
+    sizes.remove(name);
+    sizes.save(dir);
+
+    Ok(None)
+}
+
+/// Renames when possible, but restoring somewhere other than the original location can cross
+/// filesystems so that falls back to copying
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.raw_os_error() == Some(EXDEV) => {
+            if fs::symlink_metadata(from)?.is_dir() {
+                let mut options = CopyOptions::new();
+                options.content_only = true;
+
+                fs::create_dir_all(to)?;
+                fs_extra::dir::copy(from, to, &options)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            } else {
+                fs::copy(from, to)?;
+            }
+
+            remove_path(from)
+        },
+        result => result
+    }
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Finds a name that's free in `dir`. Names are also kept unique across every other trash so the
+/// aggregated trash listing can refer to items by name alone.
+fn find_unique_path(dirs: &[TrashDir], dir: &TrashDir, path: &Path) -> (PathBuf, PathBuf) {
     let mut i = 0;
     loop {
-        let name = path.file_name().unwrap().to_str().unwrap();
+        // The original name is kept in the info file, so names that aren't valid UTF-8 can be
+        // replaced with something the UI is able to refer to
+        let name = path.file_name().unwrap().to_string_lossy();
         let escaped_path = if i > 0 {
             match name.split_once('.') {
                 Some((before, after)) => format!("{before}.{i}.{after}"),
@@ -132,10 +777,12 @@ fn find_unique_path(path: &Path) -> (PathBuf, PathBuf) {
             format!("{name}")
         };
 
-        let trashinfo = info.join(format!("{escaped_path}.trashinfo"));
-        let trashpath = files.join(&escaped_path);
+        let trashinfo = dir.info.join(format!("{escaped_path}.trashinfo"));
+        let trashpath = dir.files.join(&escaped_path);
 
-        if !trashinfo.exists() && !trashpath.exists() {
+        if !trashinfo.exists() && !trashpath.exists()
+            && !dirs.iter().any(|other| other.contains(&escaped_path))
+        {
             return (trashinfo, trashpath);
         }
 
@@ -143,13 +790,65 @@ fn find_unique_path(path: &Path) -> (PathBuf, PathBuf) {
     }
 }
 
-fn get_trash_dirs() -> (PathBuf, PathBuf) {
-    let trash_base_dir = dirs::data_dir().unwrap().join("Trash");
-    (trash_base_dir.join("files"), trash_base_dir.join("info"))
-}
+const EXDEV: i32 = 18;
+const TRASH_INFO_HEADER: &'static str = "[Trash Info]";
+const TRASH_INFO_DATE_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trash_info_round_trip() {
+        let info = TrashInfo {
+            path: PathBuf::from("/home/user/a=b\nc d%.txt"),
+            deletion_date: Local.with_ymd_and_hms(2023, 4, 12, 9, 30, 5).unwrap()
+        };
+        let written = info.to_string();
 
-const TRASH_META_TEMPLATE: &'static str = "\
-[Trash Info]
-Path=$PATH
-DeletionDate=$DATE\
-";
+        assert!(written.contains("Path=/home/user/a%3Db%0Ac%20d%25.txt"));
+        assert!(written.contains("DeletionDate=2023-04-12T09:30:05"));
+        assert_eq!(TrashInfo::parse(&written).unwrap(), info);
+    }
+
+    #[test]
+    fn trash_info_round_trip_non_utf8() {
+        let info = TrashInfo {
+            path: PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9".to_vec())),
+            deletion_date: Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()
+        };
+
+        assert!(info.to_string().contains("Path=/tmp/caf%E9"));
+        assert_eq!(TrashInfo::parse(&info.to_string()).unwrap(), info);
+    }
+
+    #[test]
+    fn trash_info_relative_path() {
+        let info = TrashInfo::parse("\
+            [Trash Info]\n\
+            Path=photos/summer.jpg\n\
+            DeletionDate=2004-08-31T22:32:08\n").unwrap();
+
+        assert_eq!(info.path, PathBuf::from("photos/summer.jpg"));
+    }
+
+    #[test]
+    fn trash_info_invalid() {
+        assert!(matches!(
+            TrashInfo::parse("Path=/foo\nDeletionDate=2004-08-31T22:32:08"),
+            Err(TrashInfoError::MissingHeader)));
+        assert!(matches!(
+            TrashInfo::parse("[Trash Info]\nDeletionDate=2004-08-31T22:32:08"),
+            Err(TrashInfoError::MissingPath)));
+        assert!(matches!(
+            TrashInfo::parse("[Trash Info]\nPath=/foo\nDeletionDate=yesterday"),
+            Err(TrashInfoError::DeletionDate(_))));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn trash_put_relative_path() {
+        let result = Trash::new().put(&[ PathBuf::from("/tmp"), PathBuf::from("notes.md") ]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) 2023 Jesse Tuchsen
+ *
+ * This file is part of Aerome.
+ *
+ * Aerome is free software: you can redistribute it and/or modify it under the terms of the GNU
+ * General Public License as published by the Free Software Foundation, version 3 of the License.
+ *
+ * Aerome is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even
+ * the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+ * Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with Aerome. If not, see
+ * <https://www.gnu.org/licenses/>.
+ */
+
+use crate::models::{Account,AccountAerome,AccountLocal};
+
+/// The model a prompt runs with when it doesn't pick one
+pub const DEFAULT_PROMPT_MODEL: &str = "default";
+
+/// Roughly how many tokens `text` comes to, going by the usual four characters per token. The
+/// prompt runner doesn't pass on the counts the API reports, so usage is estimated from the text
+/// sent and received and recorded as such.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64 + 3) / 4
+}
+
+/// A prompt without the license comment at the top of it, which is never sent
+pub fn prompt_body(prompt: &str) -> &str {
+    prompt.trim_start()
+        .strip_prefix("/*")
+        .and_then(|rest| rest.split_once("*/"))
+        .map(|(_, body)| body)
+        .unwrap_or(prompt)
+}
+
+/// The model set in the options of a prompt, like "    model: gpt-4"
+pub fn prompt_model(prompt: &str) -> Option<String> {
+    prompt.lines()
+        .find_map(|line| line.trim_start().strip_prefix("model:"))
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+}
+
+/// Tells accounts apart in the usage report without showing the whole key
+pub fn account_label(account: &Account) -> String {
+    match account {
+        Account::Direct(key) => {
+            let ending = key.0.chars().skip(key.0.chars().count().saturating_sub(4)).collect::<String>();
+            format!("OpenAI key ending in {ending}")
+        },
+        Account::Aerome(AccountAerome { email, .. }) => format!("Aerome account {email}"),
+        Account::Local(AccountLocal { base_url, .. }) => format!("Local server at {base_url}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AccountDirect;
+
+    #[test]
+    fn usage_estimates() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+
+        assert_eq!(prompt_body("/*\n * License\n */\nsummary\n"), "\nsummary\n");
+        assert_eq!(prompt_body("summary\n"), "summary\n");
+
+        assert_eq!(prompt_model("summary\n    history: false\n    model: llama3\n"), Some("llama3".into()));
+        assert_eq!(prompt_model("summary\n    history: false\n"), None);
+
+        assert_eq!(account_label(&Account::Direct(AccountDirect("sk-abcdef1234".into()))),
+            "OpenAI key ending in 1234");
+    }
+}